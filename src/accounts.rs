@@ -0,0 +1,159 @@
+use color_eyre::Result;
+use eyre::WrapErr;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    logger::get_config_dir,
+    money::Money,
+    transaction::{MonthInYear, Transaction},
+};
+
+const ACCOUNTS_FILE: &str = "accounts.json";
+
+/** An account that transactions are paid from and into, matched by the transaction method */
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Account {
+    pub name: String,
+    pub currency: String,
+    /** The balance before the first transaction in the file */
+    #[serde(default)]
+    pub opening_balance: Money,
+}
+
+/** The running balance of an account in one currency */
+#[derive(Debug, Clone)]
+pub struct AccountBalance {
+    pub name: String,
+    pub currency: String,
+    pub opening: Money,
+    /** The balance at the end of each month of the summary, from the oldest */
+    pub month_ends: Vec<Money>,
+}
+
+impl AccountBalance {
+    pub fn current(&self) -> Money {
+        self.month_ends.last().copied().unwrap_or(self.opening)
+    }
+}
+
+/** The balances of all the accounts, at the end of every month from the first transaction to the last */
+#[derive(Debug, Default)]
+pub struct AccountsSummary {
+    pub months: Vec<MonthInYear>,
+    pub balances: Vec<AccountBalance>,
+}
+
+#[derive(Default)]
+pub struct Accounts {
+    accounts: Vec<Account>,
+}
+
+impl Accounts {
+    pub fn default_path() -> PathBuf {
+        get_config_dir().join(ACCOUNTS_FILE)
+    }
+
+    /** Loads the accounts, a missing file means every account opens with a balance of 0 */
+    pub fn load(file_path: &Path) -> Result<Self> {
+        if !file_path.exists() {
+            tracing::info!("no accounts file at {:?}", file_path);
+            return Ok(Self::default());
+        }
+        let file_string = fs::read_to_string(file_path)?;
+        let accounts: Vec<Account> = serde_json::from_str(&file_string)
+            .wrap_err_with(|| format!("Failed to parse accounts file {:?}", file_path))?;
+        Ok(Self { accounts })
+    }
+
+    /**
+     * Runs the balance of every account over the transactions. The accounts in the file come
+     * first, followed by the methods that are only in the transactions, a column for each currency.
+     */
+    pub fn summarize(&self, transactions: &[Transaction]) -> AccountsSummary {
+        let months = match (
+            transactions
+                .iter()
+                .map(|transaction| transaction.date)
+                .min(),
+            transactions
+                .iter()
+                .map(|transaction| transaction.date)
+                .max(),
+        ) {
+            (Some(first), Some(last)) => {
+                months_between((first.year, first.month), (last.year, last.month))
+            }
+            _ => vec![],
+        };
+        let mut balances: Vec<AccountBalance> = self
+            .accounts
+            .iter()
+            .map(|account| AccountBalance {
+                name: account.name.clone(),
+                currency: account.currency.clone(),
+                opening: account.opening_balance,
+                month_ends: vec![],
+            })
+            .collect();
+        let mut changes: HashMap<(String, String, MonthInYear), Money> = HashMap::new();
+        let mut discovered: Vec<(String, String)> = vec![];
+        for transaction in transactions {
+            for (account, change) in transaction.account_changes() {
+                let key = (account.to_string(), transaction.currency().to_string());
+                *changes
+                    .entry((
+                        key.0.clone(),
+                        key.1.clone(),
+                        (transaction.date.year, transaction.date.month),
+                    ))
+                    .or_default() += change;
+                let is_known = balances
+                    .iter()
+                    .any(|balance| balance.name == key.0 && balance.currency == key.1);
+                if !is_known && !discovered.contains(&key) {
+                    discovered.push(key);
+                }
+            }
+        }
+        discovered.sort();
+        balances.extend(
+            discovered
+                .into_iter()
+                .map(|(name, currency)| AccountBalance {
+                    name,
+                    currency,
+                    opening: Money::default(),
+                    month_ends: vec![],
+                }),
+        );
+        for balance in balances.iter_mut() {
+            let mut running = balance.opening;
+            balance.month_ends = months
+                .iter()
+                .map(|month| {
+                    running += changes
+                        .get(&(balance.name.clone(), balance.currency.clone(), *month))
+                        .copied()
+                        .unwrap_or_default();
+                    running
+                })
+                .collect();
+        }
+        AccountsSummary { months, balances }
+    }
+}
+
+fn months_between(first: MonthInYear, last: MonthInYear) -> Vec<MonthInYear> {
+    std::iter::successors(Some(first), |(year, month)| match month {
+        12 => Some((year + 1, 1)),
+        month => Some((*year, month + 1)),
+    })
+    .take_while(|month| *month <= last)
+    .collect()
+}
@@ -0,0 +1,155 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind};
+use ratatui::{
+    layout::Rect,
+    style::Style,
+    widgets::{Cell, Row, Table, TableState},
+    Frame,
+};
+
+use crate::{
+    accounts::{AccountBalance, Accounts, AccountsSummary},
+    money::Money,
+    table_design::add_design_to_table,
+    transaction::TransactionsTable,
+    TableColors,
+};
+
+const MONTH_WIDTH: u16 = 10;
+const BALANCE_WIDTH: u16 = 18;
+
+/** Shows the current balance of every account, and its balance at the end of each month */
+pub struct AccountsPage {
+    accounts: Accounts,
+    summary: AccountsSummary,
+    table_state: TableState,
+}
+
+impl AccountsPage {
+    pub fn new() -> Self {
+        Self {
+            accounts: Accounts::default(),
+            summary: AccountsSummary::default(),
+            table_state: TableState::default(),
+        }
+    }
+
+    pub fn set_accounts(&mut self, accounts: Accounts) {
+        self.accounts = accounts;
+    }
+
+    pub fn reload(&mut self, transactions_table: &TransactionsTable) {
+        self.summary = transactions_table.summarize_accounts(&self.accounts);
+        let rows_len = self.rows_len();
+        if self.table_state.selected().is_some_and(|i| i >= rows_len) {
+            self.table_state.select(rows_len.checked_sub(1));
+        }
+    }
+
+    /** The current balances row, a row for each month and the opening balances row */
+    fn rows_len(&self) -> usize {
+        self.summary.months.len() + 2
+    }
+
+    pub fn handle_key_events(&mut self, key: KeyEvent) {
+        if key.kind != KeyEventKind::Press {
+            return;
+        }
+        let last = self.rows_len() - 1;
+        let selected = self.table_state.selected();
+        let i = match key.code {
+            KeyCode::Down | KeyCode::Char('j') => selected.map_or(0, |i| (i + 1).min(last)),
+            KeyCode::Up | KeyCode::Char('k') => selected.map_or(0, |i| i.saturating_sub(1)),
+            KeyCode::PageUp => 0,
+            KeyCode::PageDown => last,
+            _ => return,
+        };
+        self.table_state.select(Some(i));
+    }
+
+    fn balance_cell<'a>(balance: Money, currency: &str, colors: &TableColors) -> Cell<'a> {
+        let cell = Cell::from(format!("\n{}\n", balance.format_in(currency)));
+        match balance.is_negative() {
+            true => cell.style(Style::new().fg(colors.overspent_fg)),
+            false => cell,
+        }
+    }
+
+    fn balances_row<'a>(
+        label: String,
+        balance_of: impl Fn(&AccountBalance) -> Money,
+        balances: &[AccountBalance],
+        colors: &TableColors,
+    ) -> Row<'a> {
+        std::iter::once(Cell::from(format!("\n{}", label)))
+            .chain(
+                balances.iter().map(|balance| {
+                    Self::balance_cell(balance_of(balance), &balance.currency, colors)
+                }),
+            )
+            .collect::<Row>()
+            .height(3)
+    }
+
+    pub fn draw(&mut self, frame: &mut Frame, area: Rect, colors: &TableColors) {
+        let balances = &self.summary.balances;
+        let header = std::iter::once("Month".to_string())
+            .chain(
+                balances
+                    .iter()
+                    .map(|balance| format!("{} {}", balance.name, balance.currency)),
+            )
+            .collect::<Row>();
+        let current = Self::balances_row(
+            "Current".to_string(),
+            AccountBalance::current,
+            balances,
+            colors,
+        )
+        .style(
+            Style::new()
+                .fg(colors.selected_row_style_fg)
+                .bg(colors.buffer_bg),
+        );
+        let month_ends = self
+            .summary
+            .months
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(month_index, (year, month))| {
+                Self::balances_row(
+                    format!("{}.{:02}", year, month),
+                    |balance| balance.month_ends[month_index],
+                    balances,
+                    colors,
+                )
+            })
+            .enumerate()
+            .map(|(i, row)| {
+                let color = match i % 2 {
+                    0 => colors.normal_row_color,
+                    _ => colors.alt_row_color,
+                };
+                row.style(Style::new().fg(colors.row_fg).bg(color))
+            });
+        let opening = Self::balances_row(
+            "Opening".to_string(),
+            |balance| balance.opening,
+            balances,
+            colors,
+        )
+        .style(
+            Style::new()
+                .fg(colors.selected_row_style_fg)
+                .bg(colors.buffer_bg),
+        );
+        let rows = std::iter::once(current)
+            .chain(month_ends)
+            .chain(std::iter::once(opening));
+        let widths = std::iter::once(MONTH_WIDTH)
+            .chain(balances.iter().map(|_| BALANCE_WIDTH))
+            .collect::<Vec<u16>>();
+        let t = add_design_to_table(Table::new(rows, widths), header, colors);
+        frame.render_stateful_widget(t, area, &mut self.table_state);
+    }
+}
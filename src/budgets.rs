@@ -0,0 +1,102 @@
+use color_eyre::Result;
+use eyre::WrapErr;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    logger::get_config_dir,
+    money::Money,
+    transaction::{Direction, DirectionAndCategory},
+};
+
+const BUDGETS_FILE: &str = "budgets.json";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum BudgetPeriod {
+    #[default]
+    Monthly,
+    Yearly,
+}
+
+/** An amount in the home currency planned for a direction, or for a category in it */
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Budget {
+    pub direction: Direction,
+    /** A budget without a category is for the whole direction */
+    pub category: Option<String>,
+    pub amount: Money,
+    #[serde(default)]
+    pub period: BudgetPeriod,
+}
+
+impl Budget {
+    fn is_for(&self, direction_and_category: &DirectionAndCategory) -> bool {
+        direction_and_category.0 == Some(self.direction)
+            && direction_and_category.1 == self.category
+    }
+}
+
+/** How much of a budget was used - yearly budgets are compared to the year up to the month */
+#[derive(Debug, Clone, Copy)]
+pub struct BudgetProgress {
+    pub budget: Money,
+    pub actual: Money,
+}
+
+impl BudgetProgress {
+    pub fn remaining(&self) -> Money {
+        self.budget - self.actual
+    }
+
+    pub fn percent_used(&self) -> Option<f64> {
+        (!self.budget.is_zero()).then(|| 100.0 * self.actual.to_f64() / self.budget.to_f64())
+    }
+
+    pub fn is_overspent(&self) -> bool {
+        self.actual > self.budget
+    }
+}
+
+#[derive(Default)]
+pub struct Budgets {
+    budgets: Vec<Budget>,
+}
+
+impl Budgets {
+    #[cfg(test)]
+    pub fn new(budgets: Vec<Budget>) -> Self {
+        Self { budgets }
+    }
+
+    pub fn default_path() -> PathBuf {
+        get_config_dir().join(BUDGETS_FILE)
+    }
+
+    /** Loads the budgets, a missing file means there are no budgets */
+    pub fn load(file_path: &Path) -> Result<Self> {
+        if !file_path.exists() {
+            tracing::info!("no budgets file at {:?}", file_path);
+            return Ok(Self::default());
+        }
+        let file_string = fs::read_to_string(file_path)?;
+        let budgets: Vec<Budget> = serde_json::from_str(&file_string)
+            .wrap_err_with(|| format!("Failed to parse budgets file {:?}", file_path))?;
+        Ok(Self { budgets })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.budgets.is_empty()
+    }
+
+    /** Returns the first budget for the direction and category */
+    pub fn get(&self, direction_and_category: &DirectionAndCategory) -> Option<&Budget> {
+        self.budgets
+            .iter()
+            .find(|budget| budget.is_for(direction_and_category))
+    }
+}
@@ -0,0 +1,209 @@
+use ratatui::{
+    layout::Rect,
+    style::{palette::tailwind, Style, Stylize},
+    symbols,
+    text::{Line, Span},
+    widgets::{Axis, Bar, BarChart, BarGroup, Block, BorderType, Chart, Dataset, GraphType},
+    Frame,
+};
+
+use crate::TableColors;
+
+const SERIES_COLORS: [tailwind::Palette; 8] = [
+    tailwind::BLUE,
+    tailwind::EMERALD,
+    tailwind::AMBER,
+    tailwind::ROSE,
+    tailwind::VIOLET,
+    tailwind::CYAN,
+    tailwind::LIME,
+    tailwind::ORANGE,
+];
+
+/** What the chart pane of the report page shows */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChartKind {
+    #[default]
+    Off,
+    /** The selected category as a bar per period */
+    Bars,
+    /** The selected category as a line over the periods */
+    Lines,
+    /** The categories of the selected direction, as a group of bars for each period */
+    Categories,
+}
+
+impl ChartKind {
+    /** The categories chart is skipped when no direction is selected, as it has no categories */
+    pub fn next(self, has_direction: bool) -> Self {
+        match self {
+            Self::Off => Self::Bars,
+            Self::Bars => Self::Lines,
+            Self::Lines if has_direction => Self::Categories,
+            Self::Lines | Self::Categories => Self::Off,
+        }
+    }
+}
+
+/** A named series of values, one for each period label */
+pub struct Series {
+    pub name: String,
+    pub values: Vec<f64>,
+}
+
+fn chart_block<'a>(title: impl Into<Line<'a>>, colors: &TableColors) -> Block<'a> {
+    Block::bordered()
+        .border_type(BorderType::Double)
+        .border_style(Style::new().fg(colors.border_color))
+        .title(title)
+}
+
+pub fn render_bars(
+    frame: &mut Frame,
+    area: Rect,
+    title: String,
+    labels: &[String],
+    values: &[f64],
+    colors: &TableColors,
+) {
+    let bars: Vec<Bar> = labels
+        .iter()
+        .zip(values)
+        .map(|(label, value)| {
+            Bar::default()
+                // bars can't go below 0, so refunds that outweigh the spending show as empty bars
+                .value(value.max(0.0).round() as u64)
+                .text_value(format!("{:.0}", value))
+                .label(Line::from(label.clone()))
+        })
+        .collect();
+    let bar_width = match labels.len() {
+        0 => 1,
+        len => (area.width.saturating_sub(2) / len as u16)
+            .saturating_sub(1)
+            .clamp(1, 9),
+    };
+    let chart = BarChart::default()
+        .block(chart_block(title, colors))
+        .data(BarGroup::default().bars(&bars))
+        .bar_width(bar_width)
+        .bar_gap(1)
+        .bar_style(Style::new().fg(colors.selected_row_style_fg))
+        .value_style(Style::new().fg(colors.header_fg).bg(colors.header_bg))
+        .bg(colors.buffer_bg);
+    frame.render_widget(chart, area);
+}
+
+/** A group of bars for each label, with a bar of each series in its color */
+pub fn render_grouped_bars(
+    frame: &mut Frame,
+    area: Rect,
+    title: String,
+    labels: &[String],
+    series: &[Series],
+    colors: &TableColors,
+) {
+    let series_color = |i: usize| SERIES_COLORS[i % SERIES_COLORS.len()].c400;
+    let groups: Vec<BarGroup> = labels
+        .iter()
+        .enumerate()
+        .map(|(period, label)| {
+            let bars: Vec<Bar> = series
+                .iter()
+                .enumerate()
+                .map(|(i, series)| {
+                    let value = series.values.get(period).copied().unwrap_or_default();
+                    Bar::default()
+                        .value(value.max(0.0).round() as u64)
+                        .text_value(format!("{:.0}", value))
+                        .style(Style::new().fg(series_color(i)))
+                })
+                .collect();
+            BarGroup::default()
+                .label(Line::from(label.clone()))
+                .bars(&bars)
+        })
+        .collect();
+    let bars_len = (labels.len() * (series.len() + 1)).max(1) as u16;
+    let bar_width = (area.width.saturating_sub(2) / bars_len).clamp(1, 9);
+    // the title doubles as the legend, naming each series in its color
+    let legend = series.iter().enumerate().map(|(i, series)| {
+        Span::styled(
+            format!("■ {} ", series.name),
+            Style::new().fg(series_color(i)),
+        )
+    });
+    let title = Line::from(
+        std::iter::once(Span::raw(title))
+            .chain(legend)
+            .collect::<Vec<Span>>(),
+    );
+    let mut chart = BarChart::default()
+        .block(chart_block(title, colors))
+        .bar_width(bar_width)
+        .bar_gap(0)
+        .group_gap(bar_width)
+        .value_style(Style::new().fg(colors.header_fg).bg(colors.header_bg))
+        .bg(colors.buffer_bg);
+    for group in groups {
+        chart = chart.data(group);
+    }
+    frame.render_widget(chart, area);
+}
+
+pub fn render_lines(
+    frame: &mut Frame,
+    area: Rect,
+    title: String,
+    labels: &[String],
+    series: &[Series],
+    colors: &TableColors,
+) {
+    let points: Vec<Vec<(f64, f64)>> = series
+        .iter()
+        .map(|series| {
+            series
+                .values
+                .iter()
+                .enumerate()
+                .map(|(i, value)| (i as f64, *value))
+                .collect()
+        })
+        .collect();
+    let datasets: Vec<Dataset> = series
+        .iter()
+        .zip(&points)
+        .enumerate()
+        .map(|(i, (series, points))| {
+            Dataset::default()
+                .name(series.name.clone())
+                .marker(symbols::Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::new().fg(SERIES_COLORS[i % SERIES_COLORS.len()].c400))
+                .data(points)
+        })
+        .collect();
+    let values = series.iter().flat_map(|series| series.values.iter());
+    let min = values.clone().copied().fold(0.0, f64::min);
+    let max = values.copied().fold(0.0, f64::max);
+    let x_labels = match (labels.first(), labels.last()) {
+        (Some(first), Some(last)) => vec![first.clone(), last.clone()],
+        _ => vec![],
+    };
+    let chart = Chart::new(datasets)
+        .block(chart_block(title, colors))
+        .x_axis(
+            Axis::default()
+                .style(Style::new().fg(colors.row_fg))
+                .bounds([0.0, labels.len().saturating_sub(1).max(1) as f64])
+                .labels(x_labels),
+        )
+        .y_axis(
+            Axis::default()
+                .style(Style::new().fg(colors.row_fg))
+                .bounds([min, max.max(min + 1.0)])
+                .labels([format!("{:.0}", min), format!("{:.0}", max)]),
+        )
+        .bg(colors.buffer_bg);
+    frame.render_widget(chart, area);
+}
@@ -0,0 +1,67 @@
+use color_eyre::Result;
+use eyre::bail;
+use std::path::PathBuf;
+
+use crate::config::Config;
+
+const USAGE: &str = "usage: hectec [transactions file] [--config <config file>] [--import <bank csv> [--profile <import profile>]] [--export-report <csv file>]";
+
+#[derive(Debug, Default)]
+pub struct Args {
+    pub file_path: Option<PathBuf>,
+    pub config_path: Option<PathBuf>,
+    pub import_path: Option<PathBuf>,
+    pub import_profile: Option<String>,
+    /** Write the report to this csv and exit, without opening the app */
+    pub export_path: Option<PathBuf>,
+}
+
+impl Args {
+    pub fn parse(args: impl Iterator<Item = String>) -> Result<Self> {
+        let mut parsed = Self::default();
+        let mut args = args.skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--config" => {
+                    parsed.config_path = Some(PathBuf::from(Self::flag_value(&mut args, &arg)?));
+                }
+                "--import" => {
+                    parsed.import_path = Some(PathBuf::from(Self::flag_value(&mut args, &arg)?));
+                }
+                "--profile" => {
+                    parsed.import_profile = Some(Self::flag_value(&mut args, &arg)?);
+                }
+                "--export-report" => {
+                    parsed.export_path = Some(PathBuf::from(Self::flag_value(&mut args, &arg)?));
+                }
+                flag if flag.starts_with("--") => bail!("Unknown flag {}\n{}", flag, USAGE),
+                _ if parsed.file_path.is_none() => parsed.file_path = Some(PathBuf::from(arg)),
+                _ => bail!("Unexpected argument {}\n{}", arg, USAGE),
+            }
+        }
+        if parsed.import_profile.is_some() && parsed.import_path.is_none() {
+            bail!("--profile is only used with --import\n{}", USAGE);
+        }
+        if parsed.import_path.is_some() && parsed.export_path.is_some() {
+            bail!(
+                "--import and --export-report can't be used together\n{}",
+                USAGE
+            );
+        }
+        Ok(parsed)
+    }
+
+    /** The transactions file given on the command line, or the one in the config */
+    pub fn transactions_file(&self, config: &Config) -> PathBuf {
+        self.file_path
+            .clone()
+            .unwrap_or_else(|| config.transactions_file.clone())
+    }
+
+    fn flag_value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String> {
+        match args.next() {
+            Some(value) => Ok(value),
+            None => bail!("Missing value for {}\n{}", flag, USAGE),
+        }
+    }
+}
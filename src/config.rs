@@ -0,0 +1,137 @@
+use color_eyre::Result;
+use eyre::{bail, WrapErr};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::PathBuf};
+
+use crate::{
+    accounts::Accounts,
+    budgets::Budgets,
+    exchange_rates::ExchangeRates,
+    logger::get_config_dir,
+    rules::Rules,
+    suggestions::AutocompletePolicy,
+    transaction::{CashFlow, DateFormat, Direction, TransactionField, DEFAULT_CURRENCY},
+};
+
+const CONFIG_FILE: &str = "config.json";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Config {
+    /** The currency the report converts every transaction into */
+    pub home_currency: String,
+    /** The transactions file to open when none is given on the command line */
+    pub transactions_file: PathBuf,
+    pub exchange_rates_file: PathBuf,
+    pub rules_file: PathBuf,
+    pub budgets_file: PathBuf,
+    pub accounts_file: PathBuf,
+    /** The directions that count as income for the net cash flow and the savings rate */
+    pub income_directions: Vec<Direction>,
+    /** The directions that count as expenses for the net cash flow and the savings rate */
+    pub expense_directions: Vec<Direction>,
+    /** Where CTRL+S in the report page writes the report */
+    pub report_file: PathBuf,
+    /** Index of the color palette to start with */
+    pub palette: usize,
+    /** Widths of the transactions table columns, missing columns use the default width */
+    pub column_widths: Vec<u16>,
+    /** How dates are shown and typed in the input page, files always use `YYYY.MM.DD` */
+    pub date_format: DateFormat,
+    /** New transactions with the same amount and details as one this many days apart are flagged */
+    pub duplicate_window_days: i64,
+    /** Autocomplete policy of each transactions table column, changed with CTRL+T */
+    pub autocomplete: Vec<AutocompletePolicy>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            home_currency: DEFAULT_CURRENCY.to_string(),
+            transactions_file: PathBuf::from("transactions.csv"),
+            exchange_rates_file: ExchangeRates::default_path(),
+            rules_file: Rules::default_path(),
+            budgets_file: Budgets::default_path(),
+            accounts_file: Accounts::default_path(),
+            income_directions: vec![Direction::In],
+            expense_directions: vec![Direction::Out],
+            report_file: PathBuf::from("report.csv"),
+            palette: 0,
+            column_widths: TransactionField::widths(),
+            date_format: DateFormat::default(),
+            duplicate_window_days: 3,
+            autocomplete: AutocompletePolicy::defaults(),
+            path: Self::default_path(),
+        }
+    }
+}
+
+impl Config {
+    pub fn default_path() -> PathBuf {
+        get_config_dir().join(CONFIG_FILE)
+    }
+
+    /**
+     * Loads the config from the given path, or from the config directory.
+     * A missing default config file is created with the default values so it can be edited.
+     */
+    pub fn load(config_path: Option<PathBuf>) -> Result<Self> {
+        let path = config_path.clone().unwrap_or_else(Self::default_path);
+        if !path.exists() {
+            if config_path.is_some() {
+                bail!("Config file not found: {:?}", path);
+            }
+            let config = Self::default();
+            config.save()?;
+            return Ok(config);
+        }
+        let file_string = fs::read_to_string(&path)?;
+        let mut config: Config = serde_json::from_str(&file_string)
+            .wrap_err_with(|| format!("Failed to parse config file {:?}", path))?;
+        config.path = path;
+        Ok(config)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(directory) = self.path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        fs::write(&self.path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /** The configured column widths, padded with the default widths */
+    pub fn column_widths(&self) -> Vec<u16> {
+        TransactionField::widths()
+            .into_iter()
+            .enumerate()
+            .map(|(i, width)| self.column_widths.get(i).copied().unwrap_or(width))
+            .collect()
+    }
+
+    /** Transfers move money between accounts, so they are never counted as income or expenses */
+    pub fn cash_flow(&self) -> CashFlow {
+        let without_transfers = |directions: &[Direction]| {
+            directions
+                .iter()
+                .copied()
+                .filter(|direction| *direction != Direction::Transfer)
+                .collect()
+        };
+        CashFlow {
+            income: without_transfers(&self.income_directions),
+            expense: without_transfers(&self.expense_directions),
+        }
+    }
+
+    /** The configured autocomplete policies, padded with the default policies */
+    pub fn autocomplete_policies(&self) -> Vec<AutocompletePolicy> {
+        AutocompletePolicy::defaults()
+            .into_iter()
+            .enumerate()
+            .map(|(i, policy)| self.autocomplete.get(i).copied().unwrap_or(policy))
+            .collect()
+    }
+}
@@ -0,0 +1,46 @@
+use crate::transaction::Transaction;
+
+/**
+ * Two transactions are near-duplicates if they are equal, or if they have the same amount,
+ * currency and details (ignoring case) within a few days of each other.
+ */
+pub fn is_near_duplicate(a: &Transaction, b: &Transaction, window_days: i64) -> bool {
+    if a.details.trim().is_empty() {
+        return false;
+    }
+    if a == b {
+        return true;
+    }
+    let days_apart = (a.date.date() - b.date.date()).whole_days().abs();
+    a.same_amount(b)
+        && a.details.trim().eq_ignore_ascii_case(b.details.trim())
+        && days_apart <= window_days
+}
+
+/**
+ * Returns the index of a transaction that the transaction at `index` duplicates.
+ * Only transactions added in this session are checked, and rows that were already saved are
+ * preferred as the original.
+ */
+pub fn find_duplicate(
+    transactions: &[Transaction],
+    index: usize,
+    window_days: i64,
+) -> Option<usize> {
+    let transaction = transactions.get(index)?;
+    if !transaction.is_new {
+        return None;
+    }
+    let candidates: Vec<usize> = transactions
+        .iter()
+        .enumerate()
+        .filter(|(i, other)| *i != index && is_near_duplicate(transaction, other, window_days))
+        .map(|(i, _)| i)
+        .collect();
+    // when two new rows duplicate each other only the later one is flagged
+    candidates
+        .iter()
+        .find(|i| !transactions[**i].is_new)
+        .or(candidates.iter().find(|i| **i < index))
+        .copied()
+}
@@ -0,0 +1,103 @@
+use color_eyre::Result;
+use csv::ReaderBuilder;
+use eyre::bail;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    logger::get_data_dir,
+    money::{minor_units, Money},
+    transaction::{FileType, SimpleDate},
+};
+
+const RATES_FILE: &str = "exchange_rates.csv";
+
+/** One unit of `from` is worth `rate` units of `to` on `date` */
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ExchangeRate {
+    pub date: SimpleDate,
+    pub from: String,
+    pub to: String,
+    pub rate: f64,
+}
+
+impl ExchangeRate {
+    /** The rate for converting `from` into `to`, using the inverse rate if the pair is reversed */
+    fn rate_for(&self, from: &str, to: &str) -> Option<f64> {
+        if self.from == from && self.to == to {
+            Some(self.rate)
+        } else if self.from == to && self.to == from && self.rate != 0.0 {
+            Some(1.0 / self.rate)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ExchangeRates {
+    rates: Vec<ExchangeRate>,
+}
+
+impl ExchangeRates {
+    #[cfg(test)]
+    pub fn new(rates: Vec<ExchangeRate>) -> Self {
+        Self { rates }
+    }
+
+    pub fn default_path() -> PathBuf {
+        get_data_dir().join(RATES_FILE)
+    }
+
+    /** Loads the rates from a csv or json file, a missing file means there are no rates */
+    pub fn load(file_path: &Path) -> Result<Self> {
+        if !file_path.exists() {
+            tracing::info!("no exchange rates file at {:?}", file_path);
+            return Ok(Self::default());
+        }
+        let rates = match FileType::new(file_path) {
+            FileType::Json => serde_json::from_str(&fs::read_to_string(file_path)?)?,
+            FileType::Csv => {
+                let mut reader = ReaderBuilder::new()
+                    .has_headers(true)
+                    .from_path(file_path)?;
+                reader.deserialize().collect::<Result<Vec<_>, _>>()?
+            }
+            FileType::Unknown => bail!("Exchange rates file type unknown"),
+        };
+        Ok(Self { rates })
+    }
+
+    /** Returns the rate between the currencies that is nearest to the given date */
+    pub fn rate(&self, from: &str, to: &str, date: &SimpleDate) -> Option<f64> {
+        if from == to {
+            return Some(1.0);
+        }
+        self.rates
+            .iter()
+            .filter_map(|exchange_rate| {
+                exchange_rate.rate_for(from, to).map(|rate| {
+                    let distance = (exchange_rate.date.date() - date.date()).whole_days().abs();
+                    (distance, rate)
+                })
+            })
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, rate)| rate)
+    }
+
+    /**
+     * Converts the amount, rounded to the minor units of `to`,
+     * or returns `None` if there is no rate for the currency pair
+     */
+    pub fn convert(&self, amount: Money, from: &str, to: &str, date: &SimpleDate) -> Option<Money> {
+        if from == to {
+            return Some(amount);
+        }
+        self.rate(from, to, date)
+            .map(|rate| Money::from_f64(amount.to_f64() * rate).round_to(minor_units(to)))
+    }
+}
@@ -0,0 +1,116 @@
+use crate::transaction::Transaction;
+
+/** The column of updates that change several fields at once, so they are never merged */
+pub const WHOLE_TRANSACTION: usize = usize::MAX;
+
+/** A change to the transactions table, holding what is needed to revert it */
+#[derive(Debug, Clone)]
+pub enum Edit {
+    Update {
+        index: usize,
+        column: usize,
+        before: Box<Transaction>,
+        after: Box<Transaction>,
+    },
+    Insert {
+        index: usize,
+        transaction: Transaction,
+    },
+    Delete {
+        index: usize,
+        transaction: Transaction,
+    },
+    /** Edits that are applied in order, and undone together as a single step */
+    Batch(Vec<Edit>),
+}
+
+impl Edit {
+    /** The edit that reverts this one */
+    pub fn inverse(self) -> Self {
+        match self {
+            Self::Update {
+                index,
+                column,
+                before,
+                after,
+            } => Self::Update {
+                index,
+                column,
+                before: after,
+                after: before,
+            },
+            Self::Insert { index, transaction } => Self::Delete { index, transaction },
+            Self::Delete { index, transaction } => Self::Insert { index, transaction },
+            Self::Batch(edits) => Self::Batch(edits.into_iter().rev().map(Self::inverse).collect()),
+        }
+    }
+
+    pub fn index(&self) -> usize {
+        match self {
+            Self::Update { index, .. }
+            | Self::Insert { index, .. }
+            | Self::Delete { index, .. } => *index,
+            Self::Batch(edits) => edits.first().map_or(0, Self::index),
+        }
+    }
+}
+
+/** Undo and redo stacks of the edits made in this session */
+#[derive(Default)]
+pub struct History {
+    undo_stack: Vec<Edit>,
+    redo_stack: Vec<Edit>,
+    /** While unsealed, consecutive updates of the same cell are merged into a single edit */
+    sealed: bool,
+}
+
+impl History {
+    pub fn record(&mut self, edit: Edit) {
+        self.redo_stack.clear();
+        if let (
+            false,
+            Some(Edit::Update {
+                index: last_index,
+                column: last_column,
+                after: last_after,
+                ..
+            }),
+            Edit::Update {
+                index,
+                column,
+                after,
+                ..
+            },
+        ) = (self.sealed, self.undo_stack.last_mut(), &edit)
+        {
+            if last_index == index && last_column == column {
+                *last_after = after.clone();
+                return;
+            }
+        }
+        self.sealed = false;
+        self.undo_stack.push(edit);
+    }
+
+    /** Stops merging updates into the last edit, called when moving to another cell */
+    pub fn seal(&mut self) {
+        self.sealed = true;
+    }
+
+    /** Returns the edit that reverts the last change */
+    pub fn undo(&mut self) -> Option<Edit> {
+        self.sealed = true;
+        self.undo_stack.pop().map(|edit| {
+            self.redo_stack.push(edit.clone());
+            edit.inverse()
+        })
+    }
+
+    /** Returns the last undone edit so it can be applied again */
+    pub fn redo(&mut self) -> Option<Edit> {
+        self.sealed = true;
+        self.redo_stack.pop().inspect(|edit| {
+            self.undo_stack.push(edit.clone());
+        })
+    }
+}
@@ -0,0 +1,225 @@
+use color_eyre::Result;
+use csv::{ReaderBuilder, StringRecord};
+use eyre::{bail, eyre, WrapErr};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    logger::get_config_dir,
+    money::Money,
+    transaction::{DateFormat, Direction, Transaction},
+};
+
+const PROFILES_FILE: &str = "import_profiles.json";
+
+/** Which amounts are expenses in the bank's export */
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum SignConvention {
+    /** Expenses are negative and income is positive */
+    #[default]
+    NegativeIsOut,
+    /** Expenses are positive and refunds are negative, as in most credit card statements */
+    PositiveIsOut,
+}
+
+/**
+ * Describes the layout of a bank or credit card csv export.
+ * Columns are given by their header name, or by their zero-based index.
+ */
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ImportProfile {
+    pub date_column: String,
+    pub amount_column: String,
+    pub details_column: String,
+    pub date_format: DateFormat,
+    pub decimal_separator: char,
+    pub sign_convention: SignConvention,
+    pub delimiter: char,
+    /** Lines to skip before the header line, for exports that start with a preamble */
+    pub skip_rows: usize,
+    /** The currency of the amounts, the home currency when it is not given */
+    pub currency: String,
+    pub method: String,
+}
+
+impl Default for ImportProfile {
+    fn default() -> Self {
+        Self {
+            date_column: "date".to_string(),
+            amount_column: "amount".to_string(),
+            details_column: "details".to_string(),
+            date_format: DateFormat::default(),
+            decimal_separator: '.',
+            sign_convention: SignConvention::default(),
+            delimiter: ',',
+            skip_rows: 0,
+            currency: "".to_string(),
+            method: "".to_string(),
+        }
+    }
+}
+
+/** The outcome of an import, with a message for every line that could not be parsed */
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub file_path: PathBuf,
+    pub transactions: Vec<Transaction>,
+    pub failures: Vec<String>,
+}
+
+impl ImportReport {
+    pub fn summary(&self) -> Vec<String> {
+        let mut lines = vec![format!(
+            "Imported {} transactions from {:?}, {} lines failed",
+            self.transactions.len(),
+            self.file_path,
+            self.failures.len()
+        )];
+        lines.extend(self.failures.iter().cloned());
+        lines
+    }
+}
+
+pub fn profiles_path() -> PathBuf {
+    get_config_dir().join(PROFILES_FILE)
+}
+
+/** Loads the named profile, a name can be omitted if there is only one profile */
+pub fn load_profile(name: Option<&str>, home_currency: &str) -> Result<ImportProfile> {
+    let path = profiles_path();
+    let file_string = fs::read_to_string(&path)
+        .wrap_err_with(|| format!("Failed to read import profiles from {:?}", path))?;
+    let mut profiles: BTreeMap<String, ImportProfile> = serde_json::from_str(&file_string)
+        .wrap_err_with(|| format!("Failed to parse import profiles {:?}", path))?;
+    let names = profiles.keys().cloned().collect::<Vec<String>>().join(", ");
+    let mut profile = match name {
+        Some(name) => profiles
+            .remove(name)
+            .ok_or_else(|| eyre!("No import profile named {}, available: {}", name, names))?,
+        None if profiles.len() == 1 => profiles.into_values().next().unwrap(),
+        None => bail!(
+            "Choose an import profile with --profile, available: {}",
+            names
+        ),
+    };
+    if profile.currency.is_empty() {
+        profile.currency = home_currency.to_string();
+    }
+    Ok(profile)
+}
+
+impl ImportProfile {
+    /** Finds a column by its header name, ignoring case, or by its index */
+    fn column_index(&self, headers: &StringRecord, column: &str) -> Result<usize> {
+        headers
+            .iter()
+            .position(|header| header.trim().eq_ignore_ascii_case(column.trim()))
+            .or_else(|| column.parse::<usize>().ok())
+            .ok_or_else(|| eyre!("Column {} is not in the headers: {:?}", column, headers))
+    }
+
+    pub fn parse_amount(&self, input: &str) -> Result<Money, String> {
+        let thousands_separator = if self.decimal_separator == ',' {
+            '.'
+        } else {
+            ','
+        };
+        let normalized: String = input
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != thousands_separator)
+            .map(|c| if c == self.decimal_separator { '.' } else { c })
+            .collect();
+        // some banks write the minus sign after the number
+        let normalized = match normalized.strip_suffix('-') {
+            Some(number) => format!("-{}", number),
+            None => normalized,
+        };
+        Money::parse(&normalized).map_err(|_| format!("invalid amount '{}'", input))
+    }
+
+    fn parse_record(
+        &self,
+        record: &StringRecord,
+        (date_index, amount_index, details_index): (usize, usize, usize),
+    ) -> Result<Transaction, String> {
+        let field = |index: usize| {
+            record
+                .get(index)
+                .map(|value| value.trim())
+                .ok_or_else(|| format!("missing column {}", index))
+        };
+        let date_input = field(date_index)?;
+        let date = self
+            .date_format
+            .parse(date_input)
+            .map_err(|e| format!("invalid date '{}': {}", date_input, e))?;
+        let amount = self.parse_amount(field(amount_index)?)?;
+        let is_out = match self.sign_convention {
+            SignConvention::NegativeIsOut => amount.is_negative(),
+            SignConvention::PositiveIsOut => !amount.is_negative() && !amount.is_zero(),
+        };
+        Ok(Transaction::new(
+            date,
+            amount.abs(),
+            field(details_index)?.to_string(),
+            "".to_string(),
+            self.method.clone(),
+            if is_out {
+                Direction::Out
+            } else {
+                Direction::In
+            },
+            self.currency.clone(),
+        ))
+    }
+
+    /** Reads the export, collecting the transactions and the lines that failed to parse */
+    pub fn import_file(&self, file_path: &Path) -> Result<ImportReport> {
+        let mut reader = ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .delimiter(self.delimiter as u8)
+            .from_path(file_path)
+            .wrap_err_with(|| format!("Failed to open {:?}", file_path))?;
+        let mut records = reader.records().skip(self.skip_rows);
+        let headers = match records.next() {
+            Some(headers) => headers?,
+            None => bail!("{:?} has no header line", file_path),
+        };
+        let indices = (
+            self.column_index(&headers, &self.date_column)?,
+            self.column_index(&headers, &self.amount_column)?,
+            self.column_index(&headers, &self.details_column)?,
+        );
+        let mut report = ImportReport {
+            file_path: file_path.to_path_buf(),
+            ..ImportReport::default()
+        };
+        for record in records {
+            let result = record.map_err(|e| e.to_string()).and_then(|record| {
+                if record.iter().all(|value| value.trim().is_empty()) {
+                    return Ok(None);
+                }
+                self.parse_record(&record, indices).map(Some).map_err(|e| {
+                    let line = record.position().map_or(0, |position| position.line());
+                    format!("line {}: {}", line, e)
+                })
+            });
+            match result {
+                Ok(Some(transaction)) => report.transactions.push(transaction),
+                Ok(None) => {}
+                Err(e) => {
+                    tracing::warn!("import {:?} {}", file_path, e);
+                    report.failures.push(e);
+                }
+            }
+        }
+        Ok(report)
+    }
+}
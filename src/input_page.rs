@@ -4,17 +4,23 @@ use ratatui::{
     layout::{Constraint, Layout, Position, Rect},
     style::{palette::tailwind, Style, Stylize},
     text::{Line, Span},
-    widgets::{Block, BorderType, Cell, Paragraph, Row, ScrollbarState, Table, TableState},
+    widgets::{Block, BorderType, Cell, Clear, Paragraph, Row, ScrollbarState, Table, TableState},
     Frame,
 };
 
 use crate::{
+    import::ImportReport,
+    popup::Popup,
+    rules::RuleChange,
+    suggestions::AutocompletePolicy,
     table_design::add_design_to_table,
     transaction::{Filter, TransactionField, TransactionsTable},
     utils::ctrl_is_pressed,
     TableColors,
 };
 
+const MAX_POPUP_ROWS: usize = 30;
+
 enum ShouldAddNewRow {
     Yes,
     No,
@@ -45,11 +51,22 @@ pub struct InputPage {
     filter: Option<Filter>,
     focus: FocusArea,
     filter_state: TableState,
-    recommended_input: Option<String>,
+    /** Ranked suggestions for the edited cell, the selected one is the recommended input */
+    suggestions: Vec<String>,
+    selected_suggestion: usize,
+    column_widths: Vec<u16>,
+    autocomplete: Vec<AutocompletePolicy>,
+    popup: Option<Popup>,
+    /** Rule changes shown in the popup, applied if the popup is closed with ENTER */
+    pending_rule_changes: Option<Vec<RuleChange>>,
 }
 
 impl InputPage {
-    pub fn new(transactions_table: TransactionsTable) -> Self {
+    pub fn new(
+        transactions_table: TransactionsTable,
+        column_widths: Vec<u16>,
+        autocomplete: Vec<AutocompletePolicy>,
+    ) -> Self {
         Self {
             table_state: TableState::default().with_selected(0),
             scroll_state: ScrollbarState::new(0),
@@ -60,7 +77,12 @@ impl InputPage {
             filter: None,
             focus: FocusArea::Table,
             filter_state: TableState::default().with_selected(0),
-            recommended_input: None,
+            suggestions: vec![],
+            selected_suggestion: 0,
+            column_widths,
+            autocomplete,
+            popup: None,
+            pending_rule_changes: None,
         }
     }
 
@@ -71,30 +93,142 @@ impl InputPage {
     }
 
     pub fn initialize_table(&mut self) -> Result<()> {
-        self.transactions_table.load()?;
+        let migration = self.transactions_table.load()?;
+        let materialized = self.transactions_table.materialize_recurring()?;
         self.reset_table(Filter::default());
+        let mut lines = vec![];
+        if !migration.unknown.is_empty() {
+            lines.push(format!(
+                "{} transactions have an unknown direction, fix them in the Direction column:",
+                migration.unknown.len()
+            ));
+            lines.extend(migration.unknown);
+        }
+        if materialized > 0 {
+            lines.push(format!(
+                "{} recurring transactions were added",
+                materialized
+            ));
+            lines.push(
+                "They are highlighted, and only saved once you edit them or keep them with CTRL+A"
+                    .to_string(),
+            );
+        }
+        if !lines.is_empty() {
+            self.popup = Some(Popup::new("Loaded", lines));
+        }
         Ok(())
     }
 
-    pub fn get_recommended_input(&self, input: &str) -> &str {
-        self.recommended_input
-            .as_ref()
-            .map(|recommended_input| {
-                let input_len = input.chars().count();
-                if input_len > recommended_input.chars().count() {
-                    ""
-                } else {
-                    &recommended_input[input_len..]
-                }
-            })
-            .unwrap_or("")
+    /** Appends the imported transactions and shows which lines could not be imported */
+    pub fn import_transactions(&mut self, import_report: ImportReport) {
+        let mut summary = import_report.summary();
+        self.transactions_table
+            .append_transactions(import_report.transactions);
+        self.reset_table(Filter::default());
+        let duplicates_count = self.transactions_table.duplicates_count();
+        if duplicates_count > 0 {
+            summary.insert(
+                1,
+                format!(
+                    "{} transactions look like duplicates, they are marked in the table",
+                    duplicates_count
+                ),
+            );
+        }
+        self.popup = Some(Popup::new("Import", summary));
+    }
+
+    /** Shows a popup if there are unresolved duplicates, returning whether it did */
+    pub fn warn_about_duplicates(&mut self) -> bool {
+        let duplicates_count = self.transactions_table.duplicates_count();
+        if duplicates_count > 0 {
+            self.popup = Some(Popup::new(
+                "Duplicates",
+                vec![
+                    format!("{} transactions look like duplicates", duplicates_count),
+                    "CTRL+G => merge into the original | CTRL+D => discard | CTRL+A => keep both"
+                        .to_string(),
+                    "Press ESC again to save & quit anyway".to_string(),
+                ],
+            ));
+        }
+        duplicates_count > 0
+    }
+
+    pub fn show_popup(&mut self, title: &str, lines: Vec<String>) {
+        self.popup = Some(Popup::new(title, lines));
+    }
+
+    pub fn has_popup(&self) -> bool {
+        self.popup.is_some()
+    }
+
+    pub fn draw_popup(&self, frame: &mut Frame, area: Rect, colors: &TableColors) {
+        if let Some(popup) = &self.popup {
+            popup.draw(frame, area, colors);
+        }
+    }
+
+    fn recommended_input(&self) -> Option<&String> {
+        self.suggestions.get(self.selected_suggestion)
+    }
+
+    /** The text shown after the input - the rest of the recommendation, or all of it if it doesn't start with the input */
+    pub fn get_recommended_input(&self, input: &str) -> String {
+        match self.recommended_input() {
+            Some(recommended_input) => match recommended_input.strip_prefix(input) {
+                Some(rest) => rest.to_string(),
+                None => format!(" → {}", recommended_input),
+            },
+            None => "".to_string(),
+        }
     }
 
     pub fn clear_recommended_input(&mut self) {
-        self.recommended_input = None;
+        self.suggestions.clear();
+        self.selected_suggestion = 0;
+    }
+
+    fn set_suggestions(&mut self, suggestions: Vec<String>) {
+        self.suggestions = suggestions;
+        self.selected_suggestion = 0;
+    }
+
+    fn next_suggestion(&mut self) {
+        if !self.suggestions.is_empty() {
+            self.selected_suggestion = (self.selected_suggestion + 1) % self.suggestions.len();
+        }
+    }
+
+    fn previous_suggestion(&mut self) {
+        if !self.suggestions.is_empty() {
+            self.selected_suggestion =
+                (self.selected_suggestion + self.suggestions.len() - 1) % self.suggestions.len();
+        }
+    }
+
+    fn autocomplete_policy(&self, column: usize) -> AutocompletePolicy {
+        self.autocomplete.get(column).copied().unwrap_or_default()
+    }
+
+    /**
+     * Moves the selected table column to its next autocomplete policy.
+     * Returns the policies of all the columns so they can be saved.
+     */
+    pub fn next_autocomplete_policy(&mut self) -> Option<Vec<AutocompletePolicy>> {
+        if self.focus != FocusArea::Table {
+            return None;
+        }
+        let (_, column) = self.table_state.selected_cell()?;
+        let policy = self.autocomplete.get_mut(column)?;
+        *policy = policy.next();
+        self.update_recommendation();
+        Some(self.autocomplete.clone())
     }
 
     fn update_editing_text(&mut self) {
+        self.transactions_table.seal_history();
         match self.focus {
             FocusArea::Table => {
                 if let Some((row, column)) = self.table_state.selected_cell() {
@@ -102,11 +236,7 @@ impl InputPage {
                         self.input = editing_text.clone();
                         self.error_msg.clear();
                         self.character_index = self.input.chars().count();
-                        self.recommended_input = self.transactions_table.update_recommended_input(
-                            row,
-                            column,
-                            &self.input,
-                        );
+                        self.update_recommendation();
                     }
                 }
             }
@@ -145,28 +275,138 @@ impl InputPage {
         }
     }
 
+    /** Selects the row of the transaction changed by undo or redo, if the filter shows it */
+    fn select_edited_transaction(&mut self, index: Option<usize>) {
+        let row = index
+            .and_then(|index| self.transactions_table.get_filtered_row(index))
+            .or(self
+                .table_state
+                .selected()
+                .map(|row| row.min(self.transactions_table.filtered_len().saturating_sub(1))));
+        self.update_selected(row);
+    }
+
+    fn undo(&mut self) {
+        let index = self.transactions_table.undo();
+        self.select_edited_transaction(index);
+    }
+
+    fn redo(&mut self) {
+        let index = self.transactions_table.redo();
+        self.select_edited_transaction(index);
+    }
+
+    /** Shows the changes the rules would make to the filtered transactions */
+    fn preview_rules(&mut self) {
+        let changes = self.transactions_table.rule_changes();
+        let date_format = &self.transactions_table.date_format;
+        let mut lines: Vec<String> = changes
+            .iter()
+            .take(MAX_POPUP_ROWS)
+            .map(|change| {
+                format!(
+                    "{} {} ({}): {}",
+                    date_format.format(&change.before.date),
+                    change.before.details,
+                    change.rule_name,
+                    change.before.changed_fields(&change.after).join(", ")
+                )
+            })
+            .collect();
+        if changes.len() > MAX_POPUP_ROWS {
+            lines.push(format!("... and {} more", changes.len() - MAX_POPUP_ROWS));
+        }
+        if changes.is_empty() {
+            lines.push("The rules don't change any of the filtered transactions".to_string());
+        } else {
+            lines.insert(
+                0,
+                format!(
+                    "{} transactions would change - ENTER => apply, any other key => cancel",
+                    changes.len()
+                ),
+            );
+            self.pending_rule_changes = Some(changes);
+        }
+        self.popup = Some(Popup::new("Rules", lines));
+    }
+
+    fn close_popup(&mut self, key: KeyEvent) {
+        self.popup = None;
+        if let Some(changes) = self.pending_rule_changes.take() {
+            if key.code == KeyCode::Enter {
+                self.transactions_table.apply_rule_changes(changes);
+                self.update_editing_text();
+            }
+        }
+    }
+
+    /** Commits the input with the recommended text, and applies the rules after editing the details */
+    fn commit_cell(&mut self) -> Result<(), String> {
+        self.commit_input(true)?;
+        if self.focus == FocusArea::Table {
+            if let Some((row, column)) = self.table_state.selected_cell() {
+                if let Some(TransactionField::Details) = TransactionField::get(column) {
+                    self.transactions_table.apply_rules(row);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn merge_duplicate(&mut self) {
+        if let Some(row) = self.table_state.selected() {
+            self.transactions_table.merge_duplicate(row);
+            self.update_selected(
+                Some(row.min(self.transactions_table.filtered_len().saturating_sub(1)))
+                    .filter(|_| self.transactions_table.filtered_len() > 0),
+            );
+        }
+    }
+
+    fn keep_transaction(&mut self) {
+        if let Some(row) = self.table_state.selected() {
+            self.transactions_table.keep_transaction(row);
+        }
+    }
+
+    /** Describes the original of the selected row if it looks like a duplicate */
+    fn duplicate_hint(&self) -> Option<String> {
+        let row = self.table_state.selected()?;
+        let original = self.transactions_table.duplicate_of(row)?;
+        Some(format!(
+            " possible duplicate of {} {} - CTRL+G merge | CTRL+D discard | CTRL+A keep",
+            self.transactions_table.date_format.format(&original.date),
+            original.details,
+        ))
+    }
+
+    fn review_hint(&self) -> Option<String> {
+        let row = self.table_state.selected()?;
+        self.transactions_table
+            .needs_review(row)
+            .then(|| " added from a recurring transaction - edit it or CTRL+A keep".to_string())
+    }
+
     fn next_row(&mut self, add_new_row_if_end: ShouldAddNewRow) {
-        let i = self
-            .table_state
-            .selected()
-            .map(|i| {
-                self.transactions_table.filtered_len().checked_sub(1).map(
-                    |last_transaction_index| {
-                        if i >= last_transaction_index {
-                            match add_new_row_if_end {
-                                ShouldAddNewRow::Yes => {
-                                    self.transactions_table.new_transaction();
-                                    last_transaction_index + 1
-                                }
-                                ShouldAddNewRow::No => last_transaction_index,
+        let i = self.table_state.selected().and_then(|i| {
+            self.transactions_table
+                .filtered_len()
+                .checked_sub(1)
+                .map(|last_transaction_index| {
+                    if i >= last_transaction_index {
+                        match add_new_row_if_end {
+                            ShouldAddNewRow::Yes => {
+                                self.transactions_table.new_transaction();
+                                last_transaction_index + 1
                             }
-                        } else {
-                            i + 1
+                            ShouldAddNewRow::No => last_transaction_index,
                         }
-                    },
-                )
-            })
-            .flatten();
+                    } else {
+                        i + 1
+                    }
+                })
+        });
         self.update_selected(i);
     }
 
@@ -200,11 +440,11 @@ impl InputPage {
     }
 
     fn next_column(&mut self) {
-        let state = match self.focus {
-            FocusArea::Table => &mut self.table_state,
-            FocusArea::Filter => &mut self.filter_state,
+        let (state, columns_len) = match self.focus {
+            FocusArea::Table => (&mut self.table_state, TransactionField::widths().len()),
+            FocusArea::Filter => (&mut self.filter_state, Filter::column_names().len()),
         };
-        if state.selected_column() == Some(TransactionField::widths().len() - 1) {
+        if state.selected_column() == Some(columns_len - 1) {
             self.select_first_column();
             self.next_row(ShouldAddNewRow::No);
         } else {
@@ -257,9 +497,11 @@ impl InputPage {
     fn update_recommendation(&mut self) {
         if self.focus == FocusArea::Table {
             if let Some((row, column)) = self.table_state.selected_cell() {
-                self.recommended_input =
+                let policy = self.autocomplete_policy(column);
+                let suggestions =
                     self.transactions_table
-                        .update_recommended_input(row, column, &self.input);
+                        .suggestions(row, column, &self.input, policy);
+                self.set_suggestions(suggestions);
             }
         }
     }
@@ -306,10 +548,11 @@ impl InputPage {
 
     fn commit_input(&mut self, with_recommended_input: bool) -> Result<(), String> {
         let input = if with_recommended_input {
-            self.recommended_input.as_deref().unwrap_or(&self.input)
+            self.recommended_input().unwrap_or(&self.input).clone()
         } else {
-            &self.input
+            self.input.clone()
         };
+        let input = input.as_str();
 
         match self.focus {
             FocusArea::Table => {
@@ -334,7 +577,7 @@ impl InputPage {
     fn update_focus(&mut self) {
         self.focus.toggle();
         if self.focus == FocusArea::Filter && self.filter.is_none() {
-            self.filter = Some(Filter::new(None, None, None));
+            self.filter = Some(Filter::new(None, None, None, None, None));
             self.filter_state.select(Some(0));
             self.select_first_column();
         }
@@ -343,10 +586,14 @@ impl InputPage {
     }
 
     pub fn handle_key_events(&mut self, key: KeyEvent) {
+        if key.kind == KeyEventKind::Press && self.popup.is_some() {
+            self.close_popup(key);
+            return;
+        }
         if key.kind == KeyEventKind::Press {
             let ctrl_pressed = ctrl_is_pressed(&key);
             match key.code {
-                KeyCode::Enter => match self.commit_input(true) {
+                KeyCode::Enter => match self.commit_cell() {
                     Ok(()) => match self.focus {
                         FocusArea::Table => {
                             self.select_first_column();
@@ -358,11 +605,11 @@ impl InputPage {
                     },
                     Err(error) => self.error_msg = error.to_string(),
                 },
-                KeyCode::Tab => match self.commit_input(true) {
+                KeyCode::Tab => match self.commit_cell() {
                     Ok(()) => self.next_column(),
                     Err(error) => self.error_msg = error.to_string(),
                 },
-                KeyCode::BackTab => match self.commit_input(true) {
+                KeyCode::BackTab => match self.commit_cell() {
                     Ok(()) => self.previous_column(),
                     Err(error) => self.error_msg = error.to_string(),
                 },
@@ -372,6 +619,13 @@ impl InputPage {
                 KeyCode::PageDown => self.last_row(),
                 KeyCode::Char('d') if ctrl_pressed => self.delete_transaction(),
                 KeyCode::Char('f') if ctrl_pressed => self.update_focus(),
+                KeyCode::Char('g') if ctrl_pressed => self.merge_duplicate(),
+                KeyCode::Char('e') if ctrl_pressed => self.preview_rules(),
+                KeyCode::Char('n') if ctrl_pressed => self.next_suggestion(),
+                KeyCode::Char('p') if ctrl_pressed => self.previous_suggestion(),
+                KeyCode::Char('a') if ctrl_pressed => self.keep_transaction(),
+                KeyCode::Char('z') if ctrl_pressed => self.undo(),
+                KeyCode::Char('y') if ctrl_pressed => self.redo(),
                 KeyCode::Backspace => self.delete_char(),
                 KeyCode::Delete => self.delete_char_forward(),
                 KeyCode::Left => self.move_cursor_left(),
@@ -402,6 +656,7 @@ impl InputPage {
         self.render_filter_bar(frame, rects[0], colors);
         self.render_transactions_table(frame, rects[1], colors);
         self.render_edit_bar(frame, rects[2], colors);
+        self.render_suggestions(frame, rects[1], colors);
         let (cursor_y, cursor_x) = (rects[2].as_position().y + 1, rects[2].as_position().x + 1);
         frame.set_cursor_position(Position::new(
             cursor_x + self.character_index as u16,
@@ -419,10 +674,11 @@ impl InputPage {
 
         let header = TransactionField::names()
             .into_iter()
-            .map(|name| Cell::from(name))
+            .map(Cell::from)
             .collect::<Row>()
             .style(header_style)
             .height(1);
+        let duplicate_flags = self.transactions_table.filtered_duplicate_flags();
         let rows = self
             .transactions_table
             .filtered_transactions()
@@ -432,11 +688,17 @@ impl InputPage {
                     0 => colors.normal_row_color,
                     _ => colors.alt_row_color,
                 };
-                let row = transaction.generate_row();
-                row.style(Style::new().fg(colors.row_fg).bg(color))
-                    .height(3)
+                let fg = if duplicate_flags.get(i).copied().unwrap_or(false) {
+                    tailwind::AMBER.c400
+                } else if transaction.needs_review {
+                    tailwind::SKY.c400
+                } else {
+                    colors.row_fg
+                };
+                let row = transaction.generate_row(&self.transactions_table.date_format);
+                row.style(Style::new().fg(fg).bg(color)).height(3)
             });
-        let t = add_design_to_table(Table::new(rows, TransactionField::widths()), header, colors);
+        let t = add_design_to_table(Table::new(rows, self.column_widths.clone()), header, colors);
         frame.render_stateful_widget(t, area, &mut self.table_state);
     }
 
@@ -445,7 +707,7 @@ impl InputPage {
 
         let header = Filter::column_names()
             .into_iter()
-            .map(|name| Cell::from(name))
+            .map(Cell::from)
             .collect::<Row>()
             .style(header_style)
             .height(1);
@@ -457,24 +719,87 @@ impl InputPage {
                 .style(Style::new().fg(colors.row_fg).bg(color))
                 .height(3);
 
-            let t = add_design_to_table(Table::new([row], Filter::column_widths()), header, colors);
+            let t = add_design_to_table(
+                Table::new([row], self.column_widths.clone()),
+                header,
+                colors,
+            );
             frame.render_stateful_widget(t, area, &mut self.filter_state);
         }
     }
 
+    /** Lists the suggestions at the bottom of the area, just above the edit bar */
+    fn render_suggestions(&self, frame: &mut Frame, area: Rect, colors: &TableColors) {
+        if self.suggestions.len() < 2 {
+            return;
+        }
+        let width = self
+            .suggestions
+            .iter()
+            .map(|suggestion| suggestion.chars().count() as u16)
+            .max()
+            .unwrap_or(0)
+            + 4;
+        let height = self.suggestions.len() as u16 + 2;
+        if area.height < height {
+            return;
+        }
+        let suggestions_area = Rect::new(
+            area.x + 1,
+            area.bottom() - height,
+            width.min(area.width.saturating_sub(1)),
+            height,
+        );
+        let lines: Vec<Line> = self
+            .suggestions
+            .iter()
+            .enumerate()
+            .map(|(i, suggestion)| {
+                let line = Line::from(format!(" {}", suggestion));
+                if i == self.selected_suggestion {
+                    line.style(Style::new().fg(colors.selected_cell_style_fg).reversed())
+                } else {
+                    line
+                }
+            })
+            .collect();
+        let suggestions = Paragraph::new(lines)
+            .style(Style::new().fg(colors.row_fg).bg(colors.buffer_bg))
+            .block(
+                Block::bordered()
+                    .border_type(BorderType::Rounded)
+                    .border_style(Style::new().fg(colors.border_color)),
+            );
+        frame.render_widget(Clear, suggestions_area);
+        frame.render_widget(suggestions, suggestions_area);
+    }
+
     fn render_edit_bar(&self, frame: &mut Frame, area: Rect, colors: &TableColors) {
+        let hint = match (self.error_msg.is_empty(), self.focus == FocusArea::Table) {
+            (true, true) => self
+                .duplicate_hint()
+                .or_else(|| self.review_hint())
+                .unwrap_or_default(),
+            _ => "".to_string(),
+        };
         let edit_text = Line::from(vec![
             Span::from(&self.input),
             Span::from(self.get_recommended_input(&self.input)).fg(tailwind::SLATE.c600),
             Span::from(&self.error_msg).fg(tailwind::ROSE.c600),
+            Span::from(hint).fg(tailwind::AMBER.c400),
         ]);
+        let mut block = Block::bordered()
+            .border_type(BorderType::Double)
+            .border_style(Style::new().fg(colors.border_color));
+        if let (FocusArea::Table, Some((_, column))) =
+            (&self.focus, self.table_state.selected_cell())
+        {
+            let title = format!(" autocomplete: {} ", self.autocomplete_policy(column));
+            block = block.title(Line::from(title).right_aligned());
+        }
         let edit_bar = Paragraph::new(edit_text)
             .style(Style::new().fg(colors.row_fg).bg(colors.buffer_bg))
-            .block(
-                Block::bordered()
-                    .border_type(BorderType::Double)
-                    .border_style(Style::new().fg(colors.border_color)),
-            );
+            .block(block);
         frame.render_widget(edit_bar, area);
     }
 }
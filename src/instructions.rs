@@ -11,25 +11,38 @@ use crate::TableColors;
 const ONELINE_INSTRUCTIONS: [&str; 1] =
     ["ESC => save & quit | CTRL+O => expand help instructions | CTRL+F => toggle filter"];
 
-const FULL_INSTRUCTIONS_HEIGHT: u16 = 17;
+const FULL_INSTRUCTIONS_HEIGHT: u16 = 30;
 const FULL_INSTRUCTIONS: [&str; FULL_INSTRUCTIONS_HEIGHT as usize] = [
     "ESC => save & quit",
     "CTRL+O => collapse help instructions",
     "CTRL+F => open or close transactions filter",
     "CTRL+C => change color",
-    "CTRL+R => toggle between insert mode and report mode",
+    "CTRL+R => switch between insert mode, report mode and accounts",
     "↑ => one line up | ↓/ENTER => one line down",
     "ENTER at last line => create new transaction",
     "SHIFT+TAB => previous-column",
     "TAB => next-column & insert recommended text",
     "PgUp => go to first row | PgDn => go to last row",
     "CTRL+D => delete selected row",
+    "CTRL+Z => undo | CTRL+Y => redo",
+    "CTRL+E => preview & apply the rules to the filtered rows",
+    "Duplicates: CTRL+G => merge into original | CTRL+D => discard | CTRL+A => keep",
+    "Recurring transactions: CTRL+A => keep as is",
+    "CTRL+N / CTRL+P => next / previous suggestion",
+    "CTRL+T => next autocomplete policy (all, prefix, same details, off) for the column",
     "DEL at end of text => remove recommended text",
     "Reports page:",
     "CTRL+↑ | CTRL+k => previous month",
     "CTRL+↓ | CTRL+j => next month",
     "CTRL+← | CTRL+h => previous category",
     "CTRL+→ | CTRL+l => next category",
+    "CTRL+U => next currency (after the last one, all currencies side by side)",
+    "CTRL+W => months / quarters / years",
+    "CTRL+S => export the report to a csv",
+    "CTRL+V => group by categories / tags",
+    "CTRL+B => chart: bars / line / categories of the direction / off",
+    "Accounts page:",
+    "↑ / ↓ | k / j => scroll the month end balances",
 ];
 
 enum State {
@@ -1,16 +1,41 @@
+mod accounts;
+mod accounts_page;
+mod budgets;
+mod charts;
+mod cli;
+mod config;
+mod duplicates;
+mod exchange_rates;
+mod history;
+mod import;
 mod input_page;
 mod instructions;
 mod logger;
+mod money;
+mod popup;
+mod recurring;
 mod report_page;
+mod rules;
+mod schema;
+mod splits;
+mod suggestions;
 mod table_design;
+mod tags;
 mod transaction;
 mod utils;
-use std::env;
-use std::path::PathBuf;
+use std::{env, path::Path};
 
+use crate::accounts::Accounts;
+use crate::accounts_page::AccountsPage;
+use crate::budgets::Budgets;
+use crate::cli::Args;
+use crate::config::Config;
+use crate::exchange_rates::ExchangeRates;
+use crate::import::{load_profile, ImportReport};
 use crate::instructions::Instructions;
 use crate::logger::initialize_logging;
-use crate::transaction::{Filter, TransactionsTable};
+use crate::rules::Rules;
+use crate::transaction::{Filter, Grouping, ReportCurrency, TransactionsTable};
 use crate::{input_page::InputPage, utils::ctrl_is_pressed};
 use color_eyre::Result;
 use crossterm::{
@@ -30,17 +55,58 @@ use style::palette::tailwind;
 fn main() -> Result<()> {
     color_eyre::install()?;
     initialize_logging()?;
-    let args: Vec<String> = env::args().collect();
+    let args = Args::parse(env::args())?;
+    let config = Config::load(args.config_path.clone())?;
+    if let Some(export_path) = &args.export_path {
+        return export_report(&args, &config, export_path);
+    }
+    let import_report = match &args.import_path {
+        Some(import_path) => {
+            let profile = load_profile(args.import_profile.as_deref(), &config.home_currency)?;
+            Some(profile.import_file(import_path)?)
+        }
+        None => None,
+    };
     let terminal = ratatui::init();
-    let default_path = PathBuf::from("transactions.csv");
-    let file_path = args
-        .get(1)
-        .map_or(default_path, |input| PathBuf::from(input));
-    let app_result = App::new(file_path).run(terminal);
+    let app_result = App::new(args, config).run(terminal, import_report);
     ratatui::restore();
     app_result
 }
 
+/** Writes the report of the transactions in the home currency to a csv, without opening the app */
+fn export_report(args: &Args, config: &Config, export_path: &Path) -> Result<()> {
+    let mut transactions_table = TransactionsTable::new(
+        args.transactions_file(config),
+        config.date_format.clone(),
+        config.duplicate_window_days,
+        config.home_currency.clone(),
+    );
+    let migration = transactions_table.load()?;
+    for unknown in &migration.unknown {
+        println!("{}", unknown);
+    }
+    let exchange_rates = ExchangeRates::load(&config.exchange_rates_file)?;
+    let missing_rates = transactions_table
+        .generate_report(&config.home_currency, &exchange_rates, Grouping::Category)
+        .write_csv(
+            export_path,
+            &ReportCurrency::Home(config.home_currency.clone()),
+            &config.cash_flow(),
+        )?;
+    println!("The report was written to {:?}", export_path);
+    if missing_rates > 0 {
+        println!("{}", missing_rates_message(missing_rates));
+    }
+    Ok(())
+}
+
+fn missing_rates_message(missing_rates: usize) -> String {
+    format!(
+        "{} transactions have no exchange rate and are left out of the sums marked with !",
+        missing_rates
+    )
+}
+
 const PALETTES: [tailwind::Palette; 4] = [
     tailwind::BLUE,
     tailwind::EMERALD,
@@ -59,6 +125,7 @@ struct TableColors {
     normal_row_color: Color,
     alt_row_color: Color,
     border_color: Color,
+    overspent_fg: Color,
 }
 
 impl TableColors {
@@ -74,6 +141,7 @@ impl TableColors {
             selected_column_style_fg: color.c400,
             selected_cell_style_fg: color.c600,
             border_color: color.c400,
+            overspent_fg: tailwind::ROSE.c400,
         }
     }
 }
@@ -81,13 +149,15 @@ impl TableColors {
 enum Page {
     Input,
     Report,
+    Accounts,
 }
 
 impl Page {
     pub fn toggle(&mut self) {
         match self {
             Self::Input => *self = Self::Report,
-            Self::Report => *self = Self::Input,
+            Self::Report => *self = Self::Accounts,
+            Self::Accounts => *self = Self::Input,
         }
     }
 }
@@ -98,21 +168,39 @@ struct App {
     scroll_state: ScrollbarState,
     input_page: InputPage,
     report_page: ReportPage,
+    accounts_page: AccountsPage,
     instructions: Instructions,
     showing_page: Page,
+    exchange_rates: ExchangeRates,
+    config: Config,
+    warned_about_duplicates: bool,
 }
 
 impl App {
-    fn new(file_path: PathBuf) -> Self {
-        let transactions_table = TransactionsTable::new(file_path);
+    fn new(args: Args, config: Config) -> Self {
+        let transactions_table = TransactionsTable::new(
+            args.transactions_file(&config),
+            config.date_format.clone(),
+            config.duplicate_window_days,
+            config.home_currency.clone(),
+        );
+        let color_index = config.palette % PALETTES.len();
         Self {
-            colors: TableColors::new(&PALETTES[0]),
-            color_index: 0,
+            colors: TableColors::new(&PALETTES[color_index]),
+            color_index,
             scroll_state: ScrollbarState::new(0),
-            input_page: InputPage::new(transactions_table),
-            report_page: ReportPage::new(),
+            input_page: InputPage::new(
+                transactions_table,
+                config.column_widths(),
+                config.autocomplete_policies(),
+            ),
+            report_page: ReportPage::new(&config.home_currency, config.cash_flow()),
+            accounts_page: AccountsPage::new(),
             instructions: Instructions::oneline(),
             showing_page: Page::Input,
+            exchange_rates: ExchangeRates::default(),
+            config,
+            warned_about_duplicates: false,
         }
     }
 
@@ -121,18 +209,72 @@ impl App {
         self.colors = TableColors::new(&PALETTES[self.color_index]);
     }
 
+    /** Changes the autocomplete policy of the selected column and saves it to the config */
+    fn next_autocomplete_policy(&mut self) {
+        if let Some(policies) = self.input_page.next_autocomplete_policy() {
+            self.config.autocomplete = policies;
+            if let Err(err) = self.config.save() {
+                tracing::error!("failed to save the config: {}", err);
+            }
+        }
+    }
+
+    fn export_report(&mut self) {
+        let file_path = &self.config.report_file;
+        let message = match self.report_page.write_csv(file_path) {
+            Ok(0) => format!("The report was written to {:?}", file_path),
+            Ok(missing_rates) => format!(
+                "The report was written to {:?}, {}",
+                file_path,
+                missing_rates_message(missing_rates)
+            ),
+            Err(err) => format!("Failed to write the report to {:?}: {}", file_path, err),
+        };
+        self.input_page.show_popup("Export", vec![message]);
+    }
+
     fn reload_report(&mut self) {
         self.report_page
-            .reload(self.input_page.transactions_table.generate_report());
+            .reload(self.input_page.transactions_table.generate_report(
+                &self.config.home_currency,
+                &self.exchange_rates,
+                self.report_page.grouping(),
+            ));
+    }
+
+    /** Regroups the report by categories or by tags, which needs the transactions */
+    fn next_report_grouping(&mut self) {
+        self.report_page.next_grouping();
+        self.reload_report();
     }
 
     fn handle_key_events(&mut self, key: KeyEvent) -> Option<()> {
         if key.kind == KeyEventKind::Press {
             let ctrl_pressed = ctrl_is_pressed(&key);
+            if self.input_page.has_popup() {
+                self.input_page.handle_key_events(key);
+                return None;
+            }
             match key.code {
+                KeyCode::Esc if !self.warned_about_duplicates => {
+                    self.warned_about_duplicates = true;
+                    if self.input_page.warn_about_duplicates() {
+                        return None;
+                    }
+                    return Some(());
+                }
                 KeyCode::Esc => return Some(()),
                 KeyCode::Char('c') if ctrl_pressed => self.next_color(),
                 KeyCode::Char('o') if ctrl_pressed => self.instructions.toggle(),
+                KeyCode::Char('t') if ctrl_pressed && matches!(self.showing_page, Page::Input) => {
+                    self.next_autocomplete_policy()
+                }
+                KeyCode::Char('s') if ctrl_pressed && matches!(self.showing_page, Page::Report) => {
+                    self.export_report()
+                }
+                KeyCode::Char('v') if ctrl_pressed && matches!(self.showing_page, Page::Report) => {
+                    self.next_report_grouping()
+                }
                 KeyCode::Char('r') if ctrl_pressed => {
                     self.showing_page.toggle();
                     match self.showing_page {
@@ -140,6 +282,9 @@ impl App {
                             self.input_page.reset_table(Filter::default());
                         }
                         Page::Report => self.reload_report(),
+                        Page::Accounts => self
+                            .accounts_page
+                            .reload(&self.input_page.transactions_table),
                     }
                 }
                 _ => match self.showing_page {
@@ -154,19 +299,35 @@ impl App {
                         }
                         self.input_page.handle_key_events(key);
                     }
+                    Page::Accounts => self.accounts_page.handle_key_events(key),
                 },
             }
         }
         None
     }
 
-    fn run(mut self, mut terminal: DefaultTerminal) -> Result<()> {
+    fn run(
+        mut self,
+        mut terminal: DefaultTerminal,
+        import_report: Option<ImportReport>,
+    ) -> Result<()> {
         enable_raw_mode()?;
         self.input_page.initialize_table()?;
+        self.input_page
+            .transactions_table
+            .set_rules(Rules::load(&self.config.rules_file)?);
+        if let Some(import_report) = import_report {
+            self.input_page.import_transactions(import_report);
+        }
+        self.exchange_rates = ExchangeRates::load(&self.config.exchange_rates_file)?;
+        self.report_page
+            .set_budgets(Budgets::load(&self.config.budgets_file)?);
+        self.accounts_page
+            .set_accounts(Accounts::load(&self.config.accounts_file)?);
         loop {
             terminal.draw(|frame| self.draw(frame))?;
             if let Event::Key(key) = event::read()? {
-                if let Some(_) = self.handle_key_events(key) {
+                if self.handle_key_events(key).is_some() {
                     self.input_page.transactions_table.save_transactions()?;
                     disable_raw_mode()?;
                     return Ok(());
@@ -187,12 +348,17 @@ impl App {
         match self.showing_page {
             Page::Input => self.input_page.draw(frame, rects[1], &self.colors),
             Page::Report => {
-                let layout = &Layout::horizontal([Constraint::Length(74), Constraint::Min(50)]);
+                let layout = &Layout::horizontal([
+                    Constraint::Length(self.report_page.width()),
+                    Constraint::Min(50),
+                ]);
                 let content_rects = layout.split(rects[1]);
                 self.report_page.draw(frame, content_rects[0], &self.colors);
                 self.input_page.draw(frame, content_rects[1], &self.colors);
             }
+            Page::Accounts => self.accounts_page.draw(frame, rects[1], &self.colors),
         }
+        self.input_page.draw_popup(frame, rects[1], &self.colors);
     }
 
     fn render_scrollbar(&mut self, frame: &mut Frame, area: Rect) {
@@ -0,0 +1,403 @@
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    fmt::{self, Display},
+    iter::Sum,
+    ops::{Add, AddAssign, Mul, Neg, Sub},
+};
+
+/** The number of decimal digits `Money` keeps, enough for the currencies with 3 minor units */
+const SCALE_DIGITS: u32 = 3;
+const SCALE: i64 = 10_i64.pow(SCALE_DIGITS);
+
+/** The number of decimal digits a currency uses, e.g. 2 for cents */
+pub fn minor_units(currency: &str) -> u32 {
+    match currency.trim().to_uppercase().as_str() {
+        "JPY" | "KRW" | "ISK" | "CLP" | "VND" | "PYG" | "UGX" | "XAF" | "XOF" => 0,
+        "BHD" | "IQD" | "JOD" | "KWD" | "LYD" | "OMR" | "TND" => 3,
+        _ => 2,
+    }
+}
+
+/**
+ * Removes the thousands separators, which must split the digits into groups of three,
+ * so e.g. `12,50` is not taken as 1250
+ */
+fn strip_separators(whole: &str) -> Option<String> {
+    let groups: Vec<&str> = whole
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .collect();
+    if let [first, rest @ ..] = groups.as_slice() {
+        let is_grouped =
+            (1..=3).contains(&first.len()) && rest.iter().all(|group| group.len() == 3);
+        if !rest.is_empty() && !is_grouped {
+            return None;
+        }
+    }
+    Some(groups.concat())
+}
+
+/**
+ * An exact decimal amount, stored as a whole number of thousandths so sums never drift.
+ * Amounts are rounded to their currency's minor units where they come from a calculation.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Money(i64);
+
+impl Money {
+    pub fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.0 < 0
+    }
+
+    pub fn abs(self) -> Self {
+        Self(self.0.abs())
+    }
+
+    pub fn from_f64(value: f64) -> Self {
+        Self((value * SCALE as f64).round() as i64)
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / SCALE as f64
+    }
+
+    /** Rounds half away from zero to the given number of decimal digits */
+    pub fn round_to(self, decimals: u32) -> Self {
+        let step = 10_i64.pow(SCALE_DIGITS.saturating_sub(decimals));
+        let half = step / 2 * self.0.signum();
+        Self((self.0 + half) / step * step)
+    }
+
+    /**
+     * Parses an amount like `1,234.56`, `-12` or `+3.5`.
+     * Commas and spaces are taken as thousands separators, between groups of three digits.
+     */
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid amount '{}'", input);
+        let trimmed = input.trim();
+        let (sign, unsigned) = match trimmed.strip_prefix('-') {
+            Some(rest) => (-1, rest),
+            None => (1, trimmed.strip_prefix('+').unwrap_or(trimmed)),
+        };
+        let (whole, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+        let whole = strip_separators(whole).ok_or_else(invalid)?;
+        if whole.is_empty() && fraction.is_empty() {
+            return Err(invalid());
+        }
+        let all_digits = |digits: &str| digits.chars().all(|c| c.is_ascii_digit());
+        if !all_digits(&whole) || !all_digits(fraction) {
+            return Err(invalid());
+        }
+        if fraction.len() > SCALE_DIGITS as usize {
+            return Err(format!(
+                "'{}' has more than {} decimal digits",
+                input, SCALE_DIGITS
+            ));
+        }
+        let whole = match whole.is_empty() {
+            true => 0,
+            false => whole.parse::<i64>().map_err(|_| invalid())?,
+        };
+        let fraction = format!("{:0<width$}", fraction, width = SCALE_DIGITS as usize)
+            .parse::<i64>()
+            .map_err(|_| invalid())?;
+        whole
+            .checked_mul(SCALE)
+            .and_then(|whole| whole.checked_add(fraction))
+            .map(|value| Self(sign * value))
+            .ok_or_else(invalid)
+    }
+
+    /** Formats the amount with exactly the given number of decimal digits */
+    pub fn format(&self, decimals: u32) -> String {
+        let rounded = self.round_to(decimals);
+        let sign = if rounded.is_negative() { "-" } else { "" };
+        let whole = rounded.0.abs() / SCALE;
+        let fraction = rounded.0.abs() % SCALE;
+        match decimals.min(SCALE_DIGITS) {
+            0 => format!("{}{}", sign, whole),
+            decimals => {
+                let fraction = fraction / 10_i64.pow(SCALE_DIGITS - decimals);
+                format!(
+                    "{}{}.{:0width$}",
+                    sign,
+                    whole,
+                    fraction,
+                    width = decimals as usize
+                )
+            }
+        }
+    }
+
+    /** Formats the amount with its currency's minor units */
+    pub fn format_in(&self, currency: &str) -> String {
+        self.format(minor_units(currency))
+    }
+}
+
+/** Shows 2 decimal digits, or 3 if the amount needs them */
+impl Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 % 10 {
+            0 => write!(f, "{}", self.format(2)),
+            _ => write!(f, "{}", self.format(3)),
+        }
+    }
+}
+
+impl Add for Money {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self(self.0 + other.0)
+    }
+}
+
+impl AddAssign for Money {
+    fn add_assign(&mut self, other: Self) {
+        self.0 += other.0;
+    }
+}
+
+impl Sub for Money {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self(self.0 - other.0)
+    }
+}
+
+impl Neg for Money {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self(-self.0)
+    }
+}
+
+impl Mul<i64> for Money {
+    type Output = Self;
+
+    fn mul(self, times: i64) -> Self {
+        Self(self.0 * times)
+    }
+}
+
+impl Sum for Money {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), Add::add)
+    }
+}
+
+impl<'a> Sum<&'a Money> for Money {
+    fn sum<I: Iterator<Item = &'a Self>>(iter: I) -> Self {
+        iter.copied().sum()
+    }
+}
+
+/** Files keep amounts as exact decimal text, so they never go through a float */
+impl Serialize for Money {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/**
+ * Floats are exact up to 15 significant digits, so larger amounts with a fraction could
+ * already be rounded. Their text can't be recovered, so they are rejected.
+ */
+const MAX_EXACT_FLOAT: f64 = 1e12;
+
+struct MoneyVisitor;
+
+impl de::Visitor<'_> for MoneyVisitor {
+    type Value = Money;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an amount")
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Money, E> {
+        value
+            .checked_mul(SCALE)
+            .map(Money)
+            .ok_or_else(|| E::custom(format!("amount {} is too large", value)))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Money, E> {
+        i64::try_from(value)
+            .map_err(|_| E::custom(format!("amount {} is too large", value)))
+            .and_then(|value| self.visit_i64(value))
+    }
+
+    /**
+     * Numbers in json, and the numeric fields of a csv, come as floats. Their shortest text is
+     * the decimal that was written, which is parsed like any other amount so extra digits are
+     * rejected instead of rounded.
+     */
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<Money, E> {
+        if !value.is_finite() || value.abs() >= MAX_EXACT_FLOAT {
+            return Err(E::custom(format!(
+                "amount {} is too large to be read exactly",
+                value
+            )));
+        }
+        Money::parse(&value.to_string()).map_err(E::custom)
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Money, E> {
+        Money::parse(value).map_err(E::custom)
+    }
+}
+
+impl<'de> Deserialize<'de> for Money {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(MoneyVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+
+    fn money(input: &str) -> Money {
+        Money::parse(input).unwrap()
+    }
+
+    #[test]
+    fn parses_signs_and_decimals() {
+        assert_eq!(money("12"), Money(12_000));
+        assert_eq!(money("-12.5"), Money(-12_500));
+        assert_eq!(money("+3.25"), Money(3_250));
+        assert_eq!(money(".5"), Money(500));
+        assert_eq!(money(" 0.125 "), Money(125));
+        assert!(Money::parse("1.2345").is_err());
+        assert!(Money::parse("").is_err());
+        assert!(Money::parse("-").is_err());
+        assert!(Money::parse("1e3").is_err());
+        assert!(Money::parse("--1").is_err());
+    }
+
+    #[test]
+    fn parses_thousands_separators_in_groups_of_three() {
+        assert_eq!(money("1,234.56"), Money(1_234_560));
+        assert_eq!(money("-1,234,567"), Money(-1_234_567_000));
+        assert_eq!(money("1 234"), Money(1_234_000));
+        assert!(Money::parse("12,50").is_err());
+        assert!(Money::parse("1,2,3").is_err());
+        assert!(Money::parse("1234,567").is_err());
+        assert!(Money::parse(",123").is_err());
+        assert!(Money::parse("1,234.5,6").is_err());
+    }
+
+    #[test]
+    fn rejects_amounts_that_overflow() {
+        assert!(Money::parse("9223372036854775807").is_err());
+        assert_eq!(money("9223372036854775.807"), Money(i64::MAX));
+    }
+
+    #[test]
+    fn rounds_half_away_from_zero() {
+        assert_eq!(money("1.005").round_to(2), money("1.01"));
+        assert_eq!(money("-1.005").round_to(2), money("-1.01"));
+        assert_eq!(money("1.004").round_to(2), money("1"));
+        assert_eq!(money("2.5").round_to(0), money("3"));
+        assert_eq!(money("-2.5").round_to(0), money("-3"));
+        assert_eq!(money("1.234").round_to(3), money("1.234"));
+    }
+
+    #[test]
+    fn knows_the_minor_units_of_currencies() {
+        assert_eq!(minor_units("JPY"), 0);
+        assert_eq!(minor_units(" kwd "), 3);
+        assert_eq!(minor_units("ILS"), 2);
+        assert_eq!(minor_units(""), 2);
+        assert_eq!(money("1234.5").format_in("JPY"), "1235");
+        assert_eq!(money("-0.1").format_in("KWD"), "-0.100");
+        assert_eq!(money("7").format_in("USD"), "7.00");
+    }
+
+    #[test]
+    fn adds_up_exactly() {
+        assert_eq!(money("0.1") + money("0.2"), money("0.3"));
+        assert_eq!(money("0.3").to_string(), "0.30");
+        assert_eq!(money("0.125").to_string(), "0.125");
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Row {
+        amount: Money,
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        let row = Row {
+            amount: money("-1234.567"),
+        };
+        let json = serde_json::to_string(&row).unwrap();
+        assert_eq!(json, r#"{"amount":"-1234.567"}"#);
+        assert_eq!(serde_json::from_str::<Row>(&json).unwrap(), row);
+        assert_eq!(
+            serde_json::from_str::<Row>(r#"{"amount":12}"#).unwrap(),
+            Row {
+                amount: money("12")
+            }
+        );
+        assert_eq!(
+            serde_json::from_str::<Row>(r#"{"amount":"1,000.5"}"#).unwrap(),
+            Row {
+                amount: money("1000.5")
+            }
+        );
+        assert_eq!(
+            serde_json::from_str::<Row>(r#"{"amount":1200.5}"#).unwrap(),
+            Row {
+                amount: money("1200.5")
+            }
+        );
+        assert!(serde_json::from_str::<Row>(r#"{"amount":1.2345}"#).is_err());
+    }
+
+    #[test]
+    fn rejects_json_amounts_that_overflow() {
+        assert!(serde_json::from_str::<Row>(r#"{"amount":9223372036854775807}"#).is_err());
+        assert!(serde_json::from_str::<Row>(r#"{"amount":18446744073709551615}"#).is_err());
+        assert!(serde_json::from_str::<Row>(r#"{"amount":1e30}"#).is_err());
+        assert!(serde_json::from_str::<Row>(r#"{"amount":1234567890123.5}"#).is_err());
+    }
+
+    #[test]
+    fn round_trips_through_csv() {
+        let rows = [
+            Row {
+                amount: money("0.1"),
+            },
+            Row {
+                amount: money("-20"),
+            },
+        ];
+        let mut writer = csv::Writer::from_writer(vec![]);
+        for row in &rows {
+            writer.serialize(row).unwrap();
+        }
+        let csv = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(csv, "amount\n0.10\n-20.00\n");
+        let read = csv::Reader::from_reader(csv.as_bytes())
+            .deserialize()
+            .collect::<Result<Vec<Row>, _>>()
+            .unwrap();
+        assert_eq!(read, rows);
+        let read = |csv: &str| {
+            csv::Reader::from_reader(csv.as_bytes())
+                .deserialize::<Row>()
+                .next()
+                .unwrap()
+        };
+        assert!(read("amount\n1.2345\n").is_err());
+        assert_eq!(read("amount\n1.234\n").unwrap().amount, money("1.234"));
+    }
+}
@@ -0,0 +1,43 @@
+use ratatui::{
+    layout::{Constraint, Flex, Layout, Rect},
+    style::Style,
+    text::Text,
+    widgets::{Block, BorderType, Clear, Paragraph, Wrap},
+    Frame,
+};
+
+use crate::TableColors;
+
+/** A message box drawn over the page, closed by the next key press */
+pub struct Popup {
+    title: String,
+    lines: Vec<String>,
+}
+
+impl Popup {
+    pub fn new(title: &str, lines: Vec<String>) -> Self {
+        Self {
+            title: title.to_string(),
+            lines,
+        }
+    }
+
+    pub fn draw(&self, frame: &mut Frame, area: Rect, colors: &TableColors) {
+        let height = (self.lines.len() as u16 + 3).min(area.height);
+        let vertical = Layout::vertical([Constraint::Length(height)]).flex(Flex::Center);
+        let horizontal = Layout::horizontal([Constraint::Percentage(70)]).flex(Flex::Center);
+        let [popup_area] = vertical.areas(area);
+        let [popup_area] = horizontal.areas(popup_area);
+        let paragraph = Paragraph::new(Text::from_iter(self.lines.clone()))
+            .wrap(Wrap { trim: false })
+            .style(Style::new().fg(colors.row_fg).bg(colors.alt_row_color))
+            .block(
+                Block::bordered()
+                    .title(format!(" {} (any key to close) ", self.title))
+                    .border_type(BorderType::Double)
+                    .border_style(Style::new().fg(colors.border_color)),
+            );
+        frame.render_widget(Clear, popup_area);
+        frame.render_widget(paragraph, popup_area);
+    }
+}
@@ -0,0 +1,283 @@
+use color_eyre::Result;
+use eyre::WrapErr;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use time::{util::days_in_year_month, Duration, Month};
+
+use crate::transaction::{SimpleDate, Transaction};
+
+const RECURRING_EXTENSION: &str = "recurring.json";
+
+/** How often a recurring transaction repeats, counted from its template's date */
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", tag = "every")]
+pub enum Schedule {
+    Week,
+    /** On the day of every month, or on the last day of shorter months */
+    Month {
+        day: u8,
+    },
+    /** On the template's month and day */
+    Year,
+}
+
+/** A transaction that repeats, like rent or a salary */
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Recurring {
+    /** What every occurrence copies, its date is when the schedule starts */
+    pub template: Transaction,
+    pub schedule: Schedule,
+    /** The last day an occurrence can fall on */
+    #[serde(default)]
+    pub end_date: Option<SimpleDate>,
+    /** The date of the last occurrence added, so deleted occurrences are not added again */
+    #[serde(default)]
+    pub materialized_until: Option<SimpleDate>,
+}
+
+fn clamped_date(year: i32, month: u8, day: u8) -> SimpleDate {
+    // the month always comes from a valid date, so it is in range
+    let days = days_in_year_month(year, Month::try_from(month).unwrap());
+    SimpleDate {
+        year,
+        month,
+        day: day.clamp(1, days),
+    }
+}
+
+impl Recurring {
+    fn is_occurrence(&self, transaction: &Transaction, date: &SimpleDate) -> bool {
+        transaction.date == *date
+            && transaction
+                .details
+                .eq_ignore_ascii_case(&self.template.details)
+            && transaction.currency() == self.template.currency()
+    }
+
+    /** Returns the dates of the occurrences from the start of the schedule up to `until` */
+    fn occurrences(&self, until: SimpleDate) -> Vec<SimpleDate> {
+        let start = self.template.date;
+        let last = self.end_date.map_or(until, |end_date| end_date.min(until));
+        let nth = |n: i64| -> Option<SimpleDate> {
+            match self.schedule {
+                Schedule::Week => start
+                    .date()
+                    .checked_add(Duration::weeks(n))
+                    .map(SimpleDate::from),
+                Schedule::Month { day } => {
+                    let months = start.year as i64 * 12 + start.month as i64 - 1 + n;
+                    let year = i32::try_from(months.div_euclid(12)).ok()?;
+                    Some(clamped_date(year, (months.rem_euclid(12) + 1) as u8, day))
+                }
+                Schedule::Year => {
+                    let year = i32::try_from(start.year as i64 + n).ok()?;
+                    Some(clamped_date(year, start.month, start.day))
+                }
+            }
+        };
+        (0..)
+            .map_while(nth)
+            // a monthly day before the template's day starts from the next month
+            .filter(|date| *date >= start)
+            .take_while(|date| *date <= last)
+            .collect()
+    }
+}
+
+#[derive(Default)]
+pub struct RecurringTransactions {
+    recurring: Vec<Recurring>,
+    /** The occurrences of each recurring transaction that were due when materializing */
+    due: Vec<Vec<SimpleDate>>,
+}
+
+impl RecurringTransactions {
+    /** The recurring transactions are kept next to the transactions file, e.g. `transactions.recurring.json` */
+    pub fn path_for(transactions_path: &Path) -> PathBuf {
+        transactions_path.with_extension(RECURRING_EXTENSION)
+    }
+
+    /** Loads the recurring transactions, a missing file means there are none */
+    pub fn load(file_path: &Path) -> Result<Self> {
+        if !file_path.exists() {
+            return Ok(Self::default());
+        }
+        let file_string = fs::read_to_string(file_path)?;
+        let recurring: Vec<Recurring> = serde_json::from_str(&file_string)
+            .wrap_err_with(|| format!("Failed to parse recurring transactions {:?}", file_path))?;
+        Ok(Self {
+            recurring,
+            due: vec![],
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.recurring.is_empty()
+    }
+
+    pub fn save(&self, file_path: &Path) -> Result<()> {
+        fs::write(file_path, serde_json::to_string_pretty(&self.recurring)?)?;
+        Ok(())
+    }
+
+    /**
+     * Returns the occurrences that are due by `today` and were not added yet, marked for review.
+     * An occurrence is skipped if a transaction with the same details and currency is on its date.
+     */
+    pub fn materialize(
+        &mut self,
+        transactions: &[Transaction],
+        today: SimpleDate,
+    ) -> Vec<Transaction> {
+        self.due = self
+            .recurring
+            .iter()
+            .map(|recurring| {
+                recurring
+                    .occurrences(today)
+                    .into_iter()
+                    .filter(|date| {
+                        recurring
+                            .materialized_until
+                            .is_none_or(|until| *date > until)
+                    })
+                    .collect()
+            })
+            .collect();
+        self.recurring
+            .iter()
+            .zip(&self.due)
+            .flat_map(|(recurring, due)| {
+                due.iter()
+                    .filter(|date| {
+                        !transactions
+                            .iter()
+                            .any(|transaction| recurring.is_occurrence(transaction, date))
+                    })
+                    .map(|date| {
+                        let mut transaction = recurring.template.clone();
+                        transaction.date = *date;
+                        transaction.is_new = true;
+                        transaction.needs_review = true;
+                        transaction
+                    })
+            })
+            .collect()
+    }
+
+    /**
+     * Moves `materialized_until` past the due occurrences that were reviewed - kept, edited or
+     * deleted - up to the first one still waiting for review, which is added again next time
+     */
+    pub fn mark_reviewed(&mut self, transactions: &[Transaction]) {
+        for (recurring, due) in self.recurring.iter_mut().zip(&self.due) {
+            let is_waiting = |date: &SimpleDate| {
+                transactions.iter().any(|transaction| {
+                    transaction.needs_review && recurring.is_occurrence(transaction, date)
+                })
+            };
+            if let Some(reviewed) = due.iter().take_while(|date| !is_waiting(date)).last() {
+                recurring.materialized_until = recurring.materialized_until.max(Some(*reviewed));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{money::Money, transaction::Direction};
+
+    fn date(year: i32, month: u8, day: u8) -> SimpleDate {
+        SimpleDate { year, month, day }
+    }
+
+    fn recurring(start: SimpleDate, schedule: Schedule) -> Recurring {
+        Recurring {
+            template: Transaction::new(
+                start,
+                Money::parse("1200").unwrap(),
+                "Rent".to_string(),
+                "home".to_string(),
+                "bank".to_string(),
+                Direction::Out,
+                "ILS".to_string(),
+            ),
+            schedule,
+            end_date: None,
+            materialized_until: None,
+        }
+    }
+
+    #[test]
+    fn monthly_days_are_clamped_to_the_end_of_shorter_months() {
+        let rent = recurring(date(2024, 1, 31), Schedule::Month { day: 31 });
+        assert_eq!(
+            rent.occurrences(date(2024, 4, 30)),
+            vec![
+                date(2024, 1, 31),
+                date(2024, 2, 29),
+                date(2024, 3, 31),
+                date(2024, 4, 30)
+            ]
+        );
+    }
+
+    #[test]
+    fn a_monthly_day_before_the_template_day_starts_next_month() {
+        let rent = recurring(date(2024, 1, 20), Schedule::Month { day: 5 });
+        assert_eq!(
+            rent.occurrences(date(2024, 3, 10)),
+            vec![date(2024, 2, 5), date(2024, 3, 5)]
+        );
+    }
+
+    #[test]
+    fn there_are_no_occurrences_after_the_end_date() {
+        let mut rent = recurring(date(2024, 1, 1), Schedule::Week);
+        rent.end_date = Some(date(2024, 1, 15));
+        assert_eq!(
+            rent.occurrences(date(2024, 12, 31)),
+            vec![date(2024, 1, 1), date(2024, 1, 8), date(2024, 1, 15)]
+        );
+    }
+
+    #[test]
+    fn occurrences_are_added_until_they_are_reviewed() {
+        let mut recurring = RecurringTransactions {
+            recurring: vec![recurring(date(2024, 1, 1), Schedule::Month { day: 1 })],
+            due: vec![],
+        };
+        // the January rent is already in the file, only February and March are added
+        let mut january = recurring.recurring[0].template.clone();
+        january.details = "RENT".to_string();
+        let mut transactions = vec![january];
+        let added = recurring.materialize(&transactions, date(2024, 3, 15));
+        assert_eq!(
+            added.iter().map(|added| added.date).collect::<Vec<_>>(),
+            vec![date(2024, 2, 1), date(2024, 3, 1)]
+        );
+        assert!(added.iter().all(|added| added.needs_review));
+
+        // February is reviewed and March is not, so only February is marked
+        transactions.extend(added);
+        transactions[1].needs_review = false;
+        recurring.mark_reviewed(&transactions);
+        assert_eq!(
+            recurring.recurring[0].materialized_until,
+            Some(date(2024, 2, 1))
+        );
+
+        // March wasn't saved, so it is added again, and a deleted February is not
+        transactions.truncate(1);
+        let added = recurring.materialize(&transactions, date(2024, 3, 15));
+        assert_eq!(
+            added.iter().map(|added| added.date).collect::<Vec<_>>(),
+            vec![date(2024, 3, 1)]
+        );
+    }
+}
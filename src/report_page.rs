@@ -1,36 +1,180 @@
+use color_eyre::Result;
 use crossterm::event::{KeyCode, KeyEvent, KeyEventKind};
+use eyre::bail;
 use ratatui::{
     layout::{Constraint, Layout, Rect},
     style::Style,
     widgets::{Cell, Row, Table, TableState},
     Frame,
 };
+use std::path::Path;
 
 use crate::{
+    budgets::{BudgetProgress, Budgets},
+    charts::{render_bars, render_grouped_bars, render_lines, ChartKind, Series},
+    exchange_rates::ExchangeRates,
     table_design::add_design_to_table,
-    transaction::{DirectionAndCategory, Filter, TransactionsReport},
+    transaction::{
+        CashFlow, DirectionAndCategory, Filter, Granularity, Grouping, ReportCurrency,
+        TransactionsReport,
+    },
     TableColors,
 };
 
 pub struct ReportPage {
     report: TransactionsReport,
     selected_category: DirectionAndCategory,
+    /** The currency shown in the report, or `None` to show all currencies side by side */
+    selected_currency: Option<ReportCurrency>,
+    granularity: Granularity,
+    grouping: Grouping,
+    chart: ChartKind,
     months_table_state: TableState,
     categories_table_state: TableState,
+    budgets: Budgets,
+    cash_flow: CashFlow,
 }
 
+const BUDGET_COLUMNS: [&str; 3] = ["Budget", "Left", "Used"];
+const BUDGET_COLUMN_WIDTH: u16 = 10;
+const SHARE_WIDTH: u16 = 6;
+const CHART_HEIGHT: u16 = 15;
+const CHANGE_COLUMNS: [&str; 2] = ["Δ prev", "Δ year"];
+const CHANGE_WIDTH: u16 = 10;
+
 impl ReportPage {
-    pub fn new() -> Self {
+    pub fn new(home_currency: &str, cash_flow: CashFlow) -> Self {
         ReportPage {
-            report: TransactionsReport::new(&vec![]),
+            report: TransactionsReport::new(
+                &[],
+                home_currency,
+                &ExchangeRates::default(),
+                Grouping::default(),
+            ),
             selected_category: (None, None),
+            selected_currency: Some(ReportCurrency::Home(home_currency.to_string())),
+            granularity: Granularity::default(),
+            grouping: Grouping::default(),
+            chart: ChartKind::default(),
             months_table_state: TableState::default(),
             categories_table_state: TableState::default(),
+            budgets: Budgets::default(),
+            cash_flow,
+        }
+    }
+
+    pub fn set_budgets(&mut self, budgets: Budgets) {
+        self.budgets = budgets;
+    }
+
+    /**
+     * Budgets are in the home currency, so they are shown only next to the home currency sums,
+     * and they are for categories, so they are hidden when grouping by tags
+     */
+    fn shows_budgets(&self) -> bool {
+        !self.budgets.is_empty()
+            && self.grouping == Grouping::Category
+            && self
+                .displayed_currencies()
+                .iter()
+                .any(|currency| matches!(currency, ReportCurrency::Home(_)))
+    }
+
+    fn budgets_width(&self) -> u16 {
+        match self.shows_budgets() {
+            true => BUDGET_COLUMN_WIDTH * BUDGET_COLUMNS.len() as u16,
+            false => 0,
+        }
+    }
+
+    fn format_budget_progress(progress: Option<BudgetProgress>, home: &str) -> Vec<String> {
+        match progress {
+            Some(progress) => vec![
+                format!("\n{}", progress.budget.format_in(home)),
+                format!("\n{}", progress.remaining().format_in(home)),
+                progress
+                    .percent_used()
+                    .map_or("\n-".to_string(), |percent| format!("\n{:.0}%", percent)),
+            ],
+            None => vec!["".to_string(); BUDGET_COLUMNS.len()],
         }
     }
 
     pub fn reload(&mut self, report: TransactionsReport) {
         self.report = report;
+        self.report.set_granularity(self.granularity);
+        if let Some(currency) = &self.selected_currency {
+            if !self.report.currencies.contains(currency) {
+                self.selected_currency = self.report.default_currency();
+            }
+        }
+    }
+
+    /** The currencies shown in the tables - either the selected one or all of them */
+    fn displayed_currencies(&self) -> Vec<ReportCurrency> {
+        match &self.selected_currency {
+            Some(currency) => vec![currency.clone()],
+            None => self.report.currencies.clone(),
+        }
+    }
+
+    /** The selected currency, or the home currency when showing all of them */
+    fn main_currency(&self) -> Option<ReportCurrency> {
+        self.selected_currency
+            .clone()
+            .or(self.report.default_currency())
+    }
+
+    /** Writes the report in the main currency to a csv, returning the missing exchange rates */
+    pub fn write_csv(&self, file_path: &Path) -> Result<usize> {
+        match self.main_currency() {
+            Some(currency) => self.report.write_csv(file_path, &currency, &self.cash_flow),
+            None => bail!("There is nothing to export"),
+        }
+    }
+
+    /** Cycles through the currencies, and then to all currencies side by side */
+    fn next_currency(&mut self) {
+        self.selected_currency = match &self.selected_currency {
+            Some(currency) => self
+                .report
+                .currencies
+                .iter()
+                .skip_while(|c| *c != currency)
+                .nth(1)
+                .cloned(),
+            None => self.report.currencies.first().cloned(),
+        };
+        self.set_category_index();
+    }
+
+    pub fn grouping(&self) -> Grouping {
+        self.grouping
+    }
+
+    /** Switches between grouping by categories and by tags, the report has to be reloaded */
+    pub fn next_grouping(&mut self) {
+        self.grouping = self.grouping.next();
+        self.selected_category = (self.selected_category.0, None);
+    }
+
+    /** Switches the rows between months, quarters and years, keeping the selected period's year */
+    fn next_granularity(&mut self) {
+        let selected_year = self
+            .report
+            .get_period_at_index(self.months_table_state.selected())
+            .map(|period| period.year());
+        self.granularity = self.granularity.next();
+        self.report.set_granularity(self.granularity);
+        let index = selected_year.and_then(|year| {
+            (0..self.report.rows_len()).find(|i| {
+                self.report
+                    .get_period_at_index(Some(*i))
+                    .is_some_and(|period| period.year() == year)
+            })
+        });
+        Self::update_selected(&mut self.months_table_state, index);
+        self.set_category_index();
     }
 
     fn update_selected(table_state: &mut TableState, i: Option<usize>) {
@@ -71,9 +215,11 @@ impl ReportPage {
     fn set_selected_category(&mut self) {
         let month_index = self.months_table_state.selected();
         let category_index = self.categories_table_state.selected();
-        self.selected_category = self
-            .report
-            .get_category_by_index_for_month_at_index(month_index, category_index)
+        self.selected_category = self.report.get_category_by_index_for_period_at_index(
+            month_index,
+            category_index,
+            &self.displayed_currencies(),
+        )
     }
 
     fn set_category_index(&mut self) {
@@ -82,7 +228,9 @@ impl ReportPage {
         }
         // else look for the category in the current month's categories and set the index to that or None
         let month_index = self.months_table_state.selected();
-        let month_categories = self.report.get_categories_for_month_by_index(month_index);
+        let month_categories = self
+            .report
+            .get_categories_for_period_by_index(month_index, &self.displayed_currencies());
         let category_index = month_categories.iter().position(|(dir, ctg)| {
             (&self.selected_category.0 == dir) && (&self.selected_category.1 == ctg)
         });
@@ -94,7 +242,10 @@ impl ReportPage {
             let number_of_months = self.report.rows_len();
             let number_of_categories = self
                 .report
-                .get_categories_for_month_by_index(self.months_table_state.selected())
+                .get_categories_for_period_by_index(
+                    self.months_table_state.selected(),
+                    &self.displayed_currencies(),
+                )
                 .len();
             match key.code {
                 KeyCode::Down | KeyCode::Char('j') => {
@@ -129,41 +280,138 @@ impl ReportPage {
                     Self::last_row(&mut self.categories_table_state, number_of_categories);
                     self.set_selected_category();
                 }
+                KeyCode::Char('u') => self.next_currency(),
+                KeyCode::Char('w') => self.next_granularity(),
+                KeyCode::Char('b') => {
+                    self.chart = self.chart.next(self.selected_category.0.is_some())
+                }
                 _ => return None,
             }
         }
         Some(())
     }
 
+    fn months_amount_width(&self) -> u16 {
+        match self.displayed_currencies().len() {
+            0 | 1 => 20,
+            _ => 12,
+        }
+    }
+
+    fn months_width(&self) -> u16 {
+        let currencies_len = self.displayed_currencies().len().max(1) as u16;
+        12 + self.months_amount_width() * currencies_len
+    }
+
+    /** The width the report needs, which grows when showing currencies side by side */
+    pub fn width(&self) -> u16 {
+        let currencies_len = self.displayed_currencies().len().max(1) as u16;
+        self.months_width()
+            + 32
+            + 10 * currencies_len
+            + SHARE_WIDTH
+            + CHANGE_WIDTH * CHANGE_COLUMNS.len() as u16
+            + self.budgets_width()
+    }
+
     pub fn draw(&mut self, frame: &mut Frame, area: Rect, colors: &TableColors) {
-        let layout = &Layout::horizontal([Constraint::Length(32), Constraint::Min(42)]);
-        let rects = layout.split(area);
+        let chart_height = match self.chart {
+            ChartKind::Off => 0,
+            _ => CHART_HEIGHT,
+        };
+        let vertical = &Layout::vertical([Constraint::Min(9), Constraint::Length(chart_height)]);
+        let [tables_area, chart_area] = vertical.areas(area);
+        let layout =
+            &Layout::horizontal([Constraint::Length(self.months_width()), Constraint::Min(42)]);
+        let rects = layout.split(tables_area);
 
         self.render_months(frame, rects[0], colors);
         self.render_categories(frame, rects[1], colors);
+        self.render_chart(frame, chart_area, colors);
+    }
+
+    fn selected_category_name(&self) -> String {
+        format!(
+            "{}-{}",
+            self.selected_category
+                .0
+                .and_then(|dir| dir.to_string().chars().next())
+                .unwrap_or('*'),
+            self.selected_category.1.clone().unwrap_or("*".to_string())
+        )
+    }
+
+    /** Plots the selected category, or the categories of its direction, over the periods in the main currency */
+    fn render_chart(&self, frame: &mut Frame, area: Rect, colors: &TableColors) {
+        let Some(currency) = self.main_currency() else {
+            return;
+        };
+        let labels = self.report.get_period_labels();
+        let title = format!(" {} {} ", self.selected_category_name(), currency);
+        match self.chart {
+            ChartKind::Off => {}
+            ChartKind::Bars => {
+                let values = self
+                    .report
+                    .get_period_values(&self.selected_category, &currency);
+                render_bars(frame, area, title, &labels, &values, colors);
+            }
+            ChartKind::Lines => {
+                let series = [Series {
+                    name: self.selected_category_name(),
+                    values: self
+                        .report
+                        .get_period_values(&self.selected_category, &currency),
+                }];
+                render_lines(frame, area, title, &labels, &series, colors);
+            }
+            ChartKind::Categories => match self.selected_category.0 {
+                Some(direction) => {
+                    let series: Vec<Series> = self
+                        .report
+                        .get_direction_categories(direction)
+                        .into_iter()
+                        .map(|direction_and_category| Series {
+                            values: self
+                                .report
+                                .get_period_values(&direction_and_category, &currency),
+                            name: direction_and_category.1.unwrap_or_default(),
+                        })
+                        .collect();
+                    let title = format!(" {} categories {} ", direction, currency);
+                    render_grouped_bars(frame, area, title, &labels, &series, colors);
+                }
+                None => {
+                    let title = " select a direction to compare its categories ".to_string();
+                    render_grouped_bars(frame, area, title, &[], &[], colors);
+                }
+            },
+        }
     }
 
     fn render_months(&mut self, frame: &mut Frame, area: Rect, colors: &TableColors) {
         let date_width = 10;
-        let amount_width = 20;
-        let header = Row::new(vec![
-            "Dates".to_string(),
-            format!(
-                "{}-{}",
-                self.selected_category
-                    .0
-                    .clone()
-                    .map(|dir| dir.chars().next())
-                    .flatten()
-                    .unwrap_or('*'),
-                self.selected_category.1.clone().unwrap_or("*".to_string())
-            ),
-        ]);
+        let currencies = self.displayed_currencies();
+        let amount_width = self.months_amount_width();
+        let category_name = self.selected_category_name();
+        let header = Row::new(
+            std::iter::once("Dates".to_string())
+                .chain(
+                    currencies
+                        .iter()
+                        .map(|currency| format!("{} {}", category_name, currency)),
+                )
+                .collect::<Vec<String>>(),
+        );
         let rows = self
             .report
-            .get_month_rows(&self.selected_category)
+            .get_period_rows(&self.selected_category, &currencies)
             .into_iter()
-            .map(|(month, value)| vec![month, format!("\n{:02.2}", value)])
+            .map(|(month, values)| {
+                std::iter::once(month)
+                    .chain(values.iter().map(|value| format!("\n{}", value)))
+                    .collect::<Vec<String>>()
+            })
             .enumerate()
             .map(|(i, row)| {
                 let color = match i % 2 {
@@ -175,45 +423,140 @@ impl ReportPage {
                 row.style(Style::new().fg(colors.row_fg).bg(color))
                     .height(3)
             });
-        let widths = vec![date_width, amount_width];
+        let widths = std::iter::once(date_width)
+            .chain(currencies.iter().map(|_| amount_width))
+            .collect::<Vec<u16>>();
         let t = add_design_to_table(Table::new(rows, widths), header, colors);
         frame.render_stateful_widget(t, area, &mut self.months_table_state);
     }
 
     fn render_categories(&mut self, frame: &mut Frame, area: Rect, colors: &TableColors) {
-        let header = Row::new(vec!["Category", "Sum"]);
+        let currencies = self.displayed_currencies();
+        let shows_budgets = self.shows_budgets();
+        let budget_headers = BUDGET_COLUMNS
+            .iter()
+            .filter(|_| shows_budgets)
+            .map(|name| name.to_string());
+        let header_cells = std::iter::once(self.grouping.to_string())
+            .chain(currencies.iter().map(|currency| {
+                let missing_rates = self.report.missing_rates_count();
+                match currency {
+                    ReportCurrency::Home(_) if missing_rates > 0 => {
+                        format!("{} !{}", currency, missing_rates)
+                    }
+                    _ => format!("Sum {}", currency),
+                }
+            }))
+            .chain(std::iter::once("Share".to_string()))
+            .chain(CHANGE_COLUMNS.iter().map(|name| name.to_string()))
+            .chain(budget_headers)
+            .collect::<Vec<String>>();
+        let header_len = header_cells.len();
+        let header = Row::new(header_cells);
+        let home = self
+            .report
+            .default_currency()
+            .map(|currency| currency.code().to_string())
+            .unwrap_or_default();
         let index = self.months_table_state.selected();
+        let categories = self
+            .report
+            .get_categories_for_period_by_index(index, &currencies);
         let rows = self
             .report
-            .get_category_rows_for_month_by_index(index)
+            .get_category_rows_for_period_by_index(
+                index,
+                &currencies,
+                self.main_currency().as_ref(),
+            )
             .into_iter()
+            .zip(categories.iter())
             .enumerate()
-            .map(|(i, row)| {
+            .map(|(i, (row, direction_and_category))| {
                 let color = match i % 2 {
                     0 => colors.normal_row_color,
                     _ => colors.alt_row_color,
                 };
+                let progress = shows_budgets
+                    .then(|| {
+                        self.report.get_budget_progress(
+                            direction_and_category,
+                            index,
+                            &self.budgets,
+                        )
+                    })
+                    .flatten();
+                let fg = match progress {
+                    Some(progress) if progress.is_overspent() => colors.overspent_fg,
+                    _ => colors.row_fg,
+                };
+                let budget_cells = match shows_budgets {
+                    true => Self::format_budget_progress(progress, &home),
+                    false => vec![],
+                };
 
                 row.into_iter()
-                    .map(|v| Cell::from(v))
+                    .chain(budget_cells)
+                    .map(Cell::from)
                     .collect::<Row>()
-                    .style(Style::new().fg(colors.row_fg).bg(color))
+                    .style(Style::new().fg(fg).bg(color))
                     .height(3)
             });
+        let summary_rows = self
+            .report
+            .get_cash_flow_rows(index, &currencies, &self.cash_flow)
+            .into_iter()
+            .chain(self.report.get_account_rows(index, &currencies))
+            .map(|row| {
+                row.into_iter()
+                    .chain(std::iter::repeat("".to_string()))
+                    .take(header_len)
+                    .map(Cell::from)
+                    .collect::<Row>()
+                    .style(
+                        Style::new()
+                            .fg(colors.selected_row_style_fg)
+                            .bg(colors.buffer_bg),
+                    )
+                    .height(3)
+            });
+        let rows = rows.chain(summary_rows);
         let amount_width = 10;
-        let category_width = area.as_size().width.max(amount_width + 4) - amount_width - 2;
-        let widths = vec![category_width, amount_width];
+        let amounts_width = amount_width * currencies.len().max(1) as u16
+            + SHARE_WIDTH
+            + CHANGE_WIDTH * CHANGE_COLUMNS.len() as u16
+            + self.budgets_width();
+        let category_width = area.as_size().width.max(amounts_width + 4) - amounts_width - 2;
+        let widths = std::iter::once(category_width)
+            .chain(currencies.iter().map(|_| amount_width))
+            .chain(std::iter::once(SHARE_WIDTH))
+            .chain(CHANGE_COLUMNS.iter().map(|_| CHANGE_WIDTH))
+            .chain(
+                BUDGET_COLUMNS
+                    .iter()
+                    .filter(|_| shows_budgets)
+                    .map(|_| BUDGET_COLUMN_WIDTH),
+            )
+            .collect::<Vec<u16>>();
         let t = add_design_to_table(Table::new(rows, widths), header, colors);
         frame.render_stateful_widget(t, area, &mut self.categories_table_state);
     }
 
     pub(crate) fn get_report_filter(&self) -> Filter {
+        let (category, tag) = match self.grouping {
+            Grouping::Category => (self.selected_category.1.clone(), None),
+            Grouping::Tag => (None, self.selected_category.1.clone()),
+        };
         Filter::new(
-            self.selected_category.clone().0,
-            self.selected_category.clone().1,
+            self.selected_category.0,
+            category,
+            tag,
             self.report
-                .get_month_at_index(self.months_table_state.selected())
+                .get_period_at_index(self.months_table_state.selected())
                 .cloned(),
+            self.selected_currency
+                .as_ref()
+                .and_then(|currency| currency.filter_currency()),
         )
     }
 }
@@ -0,0 +1,125 @@
+use color_eyre::Result;
+use eyre::WrapErr;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    logger::get_config_dir,
+    transaction::{Direction, Transaction},
+};
+
+const RULES_FILE: &str = "rules.json";
+
+/**
+ * Sets fields of transactions whose details match a regex.
+ * Rules with a higher priority win, and rules with the same priority apply in file order.
+ */
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Rule {
+    pub name: String,
+    /** A regex matched against the details, e.g. `^SUPERSAL` */
+    pub details: String,
+    #[serde(default)]
+    pub priority: i32,
+    pub category: Option<String>,
+    pub method: Option<String>,
+    pub direction: Option<Direction>,
+    pub currency: Option<String>,
+}
+
+impl Rule {
+    /** Returns the transaction with the rule's fields set */
+    fn apply(&self, transaction: &Transaction) -> Transaction {
+        let mut updated = transaction.clone();
+        if let Err(e) = updated.set_fields(
+            self.category.as_deref(),
+            self.method.as_deref(),
+            self.direction,
+            self.currency.as_deref(),
+        ) {
+            tracing::warn!(
+                "rule {} kept the currency of '{}': {}",
+                self.name,
+                transaction.details,
+                e
+            );
+        }
+        updated
+    }
+}
+
+/** A change that a rule makes to a transaction */
+#[derive(Debug, Clone)]
+pub struct RuleChange {
+    pub index: usize,
+    pub rule_name: String,
+    pub before: Transaction,
+    pub after: Transaction,
+}
+
+#[derive(Default)]
+pub struct Rules {
+    rules: Vec<(Rule, Regex)>,
+}
+
+impl Rules {
+    pub fn default_path() -> PathBuf {
+        get_config_dir().join(RULES_FILE)
+    }
+
+    /** Loads the rules sorted by precedence, a missing file means there are no rules */
+    pub fn load(file_path: &Path) -> Result<Self> {
+        if !file_path.exists() {
+            tracing::info!("no rules file at {:?}", file_path);
+            return Ok(Self::default());
+        }
+        let file_string = fs::read_to_string(file_path)?;
+        let rules: Vec<Rule> = serde_json::from_str(&file_string)
+            .wrap_err_with(|| format!("Failed to parse rules file {:?}", file_path))?;
+        let mut rules = rules
+            .into_iter()
+            .map(|rule| {
+                let regex = Regex::new(&rule.details)
+                    .wrap_err_with(|| format!("Invalid regex in rule {}", rule.name))?;
+                Ok((rule, regex))
+            })
+            .collect::<Result<Vec<(Rule, Regex)>>>()?;
+        // sort_by_key is stable, so rules with the same priority keep their file order
+        rules.sort_by_key(|(rule, _)| -rule.priority);
+        Ok(Self { rules })
+    }
+
+    /** Returns the rule with the highest precedence that matches the transaction */
+    pub fn matching_rule(&self, transaction: &Transaction) -> Option<&Rule> {
+        self.rules
+            .iter()
+            .find(|(_, regex)| regex.is_match(&transaction.details))
+            .map(|(rule, _)| rule)
+    }
+
+    /** Returns the transaction after applying the matching rule */
+    pub fn apply(&self, transaction: &Transaction) -> Transaction {
+        self.matching_rule(transaction)
+            .map_or(transaction.clone(), |rule| rule.apply(transaction))
+    }
+
+    /** Returns the change the matching rule would make, if it changes anything */
+    pub fn change(&self, index: usize, transaction: &Transaction) -> Option<RuleChange> {
+        let rule = self.matching_rule(transaction)?;
+        let after = rule.apply(transaction);
+        if after.same_fields(transaction) {
+            return None;
+        }
+        Some(RuleChange {
+            index,
+            rule_name: rule.name.clone(),
+            before: transaction.clone(),
+            after,
+        })
+    }
+}
@@ -0,0 +1,185 @@
+use color_eyre::Result;
+use csv::StringRecord;
+use eyre::{bail, eyre};
+use serde::de::{value::StrDeserializer, DeserializeOwned};
+
+use crate::{money::Money, splits::Splits, transaction::SimpleDate};
+
+/**
+ * The version of the csv transactions file, written in its first line as `#schema:2`.
+ * Version 1 files have no such line, and were written before the destination, splits, tags and
+ * notes columns. Files of a newer version are not loaded, so their columns are never lost.
+ */
+pub const SCHEMA_VERSION: u32 = 2;
+const SCHEMA_MARKER: &str = "#schema:";
+
+/** The columns of a transaction, in the order they are written */
+pub const COLUMNS: [&str; 11] = [
+    "date",
+    "amount",
+    "details",
+    "category",
+    "method",
+    "direction",
+    "currency",
+    "destination",
+    "splits",
+    "tags",
+    "notes",
+];
+/** A file without one of these can't be loaded, the other columns default to empty */
+const REQUIRED_COLUMNS: [&str; 3] = ["date", "amount", "direction"];
+
+pub fn version_line() -> String {
+    format!("{}{}", SCHEMA_MARKER, SCHEMA_VERSION)
+}
+
+/** Reads the file as text, naming the line of the first invalid UTF-8 byte */
+pub fn read_text(bytes: Vec<u8>) -> Result<String> {
+    String::from_utf8(bytes).map_err(|error| {
+        let valid = &error.as_bytes()[..error.utf8_error().valid_up_to()];
+        let line = valid.iter().filter(|byte| **byte == b'\n').count() + 1;
+        eyre!("line {}: invalid UTF-8", line)
+    })
+}
+
+/** Names the line of a record that can't be split into its fields */
+pub fn describe_record_error(skipped_lines: u64, error: csv::Error) -> eyre::Report {
+    let line = error.position().map_or(0, |position| position.line()) + skipped_lines;
+    match error.kind() {
+        csv::ErrorKind::UnequalLengths {
+            expected_len, len, ..
+        } => eyre!(
+            "line {}: {} fields, while the header has {}",
+            line,
+            len,
+            expected_len
+        ),
+        _ => eyre!("line {}: {}", line, error),
+    }
+}
+
+/**
+ * Returns the file's schema version and the csv after the schema line.
+ * Only newer versions are read differently, by refusing them: columns are found by their headers,
+ * so a version 1 file is read like a version 2 file whose newer columns are empty.
+ */
+pub fn read_version(file_string: &str) -> Result<(u32, &str)> {
+    let Some(rest) = file_string.strip_prefix(SCHEMA_MARKER) else {
+        return Ok((1, file_string));
+    };
+    let (version, csv) = rest.split_once('\n').unwrap_or((rest, ""));
+    let version = version.trim();
+    let Ok(version) = version.parse::<u32>() else {
+        bail!("line 1: invalid schema version '{}'", version);
+    };
+    if version > SCHEMA_VERSION {
+        bail!(
+            "line 1: the file has schema version {}, this version of hectec reads up to {}",
+            version,
+            SCHEMA_VERSION
+        );
+    }
+    Ok((version, csv))
+}
+
+/**
+ * Where the columns of a csv file are. Columns can be in any order, and the columns that are
+ * not in the schema are kept as they are, so they are written back when saving.
+ */
+pub struct CsvLayout {
+    known_headers: StringRecord,
+    known_indices: Vec<usize>,
+    extra_columns: Vec<String>,
+    extra_indices: Vec<usize>,
+}
+
+impl CsvLayout {
+    pub fn new(headers: &StringRecord) -> Result<Self> {
+        for (i, header) in headers.iter().enumerate() {
+            if headers.iter().take(i).any(|other| other == header) {
+                bail!("column '{}' appears more than once", header);
+            }
+        }
+        if let Some(missing) = REQUIRED_COLUMNS
+            .iter()
+            .find(|required| !headers.iter().any(|header| header == **required))
+        {
+            bail!("column '{}' is missing", missing);
+        }
+        let (known_indices, extra_indices): (Vec<usize>, Vec<usize>) =
+            (0..headers.len()).partition(|i| COLUMNS.contains(&&headers[*i]));
+        Ok(Self {
+            known_headers: known_indices.iter().map(|i| &headers[*i]).collect(),
+            extra_columns: extra_indices
+                .iter()
+                .map(|i| headers[*i].to_string())
+                .collect(),
+            known_indices,
+            extra_indices,
+        })
+    }
+
+    /** The headers of the known columns, which a transaction is deserialized with */
+    pub fn known_headers(&self) -> &StringRecord {
+        &self.known_headers
+    }
+
+    pub fn extra_columns(&self) -> &[String] {
+        &self.extra_columns
+    }
+
+    /** Separates the known fields of a record from the values of the extra columns */
+    pub fn split(&self, record: &StringRecord) -> (StringRecord, Vec<String>) {
+        let field = |i: &usize| record.get(*i).unwrap_or_default();
+        (
+            self.known_indices.iter().map(field).collect(),
+            self.extra_indices
+                .iter()
+                .map(|i| field(i).to_string())
+                .collect(),
+        )
+    }
+
+    /**
+     * The errors of the columns with their own parsing don't say which column failed,
+     * so their fields are parsed on their own to find it.
+     */
+    fn failing_column(&self, known: &StringRecord) -> Option<&str> {
+        fn parses<T: DeserializeOwned>(value: &str) -> bool {
+            T::deserialize(StrDeserializer::<serde::de::value::Error>::new(value)).is_ok()
+        }
+        self.known_headers
+            .iter()
+            .zip(known.iter())
+            .find(|(header, value)| match *header {
+                "date" => !parses::<SimpleDate>(value),
+                "amount" => !parses::<Money>(value),
+                "splits" => !parses::<Splits>(value),
+                _ => false,
+            })
+            .map(|(header, _)| header)
+    }
+
+    /** Names the line and the column of a record's field that failed to deserialize */
+    pub fn describe_error(
+        &self,
+        line: u64,
+        known: &StringRecord,
+        error: csv::Error,
+    ) -> eyre::Report {
+        match error.kind() {
+            csv::ErrorKind::Deserialize { err, .. } => {
+                match err
+                    .field()
+                    .and_then(|field| self.known_headers.get(field as usize))
+                    .or_else(|| self.failing_column(known))
+                {
+                    Some(column) => eyre!("line {}, column '{}': {}", line, column, err.kind()),
+                    None => eyre!("line {}: {}", line, err.kind()),
+                }
+            }
+            _ => eyre!("line {}: {}", line, error),
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use crate::money::{minor_units, Money};
+
+/** A part of a transaction's amount that belongs to its own category */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Split {
+    pub category: String,
+    pub amount: Money,
+}
+
+/**
+ * The allocations of a split transaction, written as `groceries=120.50; household=30`.
+ * Files keep them in the same text form, so they fit in a csv column.
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Splits(Vec<Split>);
+
+impl Splits {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Split> {
+        self.0.iter()
+    }
+
+    pub fn total(&self) -> Money {
+        self.0.iter().map(|split| split.amount).sum()
+    }
+
+    /** Checks that the splits add up to the transaction's amount, in the currency's minor units */
+    pub fn validate(&self, amount: Money, currency: &str) -> Result<(), String> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        let decimals = minor_units(currency);
+        if let Some(split) = self
+            .iter()
+            .find(|split| split.amount.round_to(decimals) != split.amount)
+        {
+            return Err(format!(
+                "{} has more than {} decimal digits",
+                split.category, decimals
+            ));
+        }
+        if self.total() != amount {
+            return Err(format!(
+                "the splits add up to {}, not to the amount {}",
+                self.total().format(decimals),
+                amount.format(decimals)
+            ));
+        }
+        Ok(())
+    }
+
+    /** Shows the amounts with the currency's minor units */
+    pub fn format_in(&self, currency: &str) -> String {
+        self.iter()
+            .map(|split| format!("{}={}", split.category, split.amount.format_in(currency)))
+            .collect::<Vec<String>>()
+            .join("; ")
+    }
+}
+
+impl FromStr for Splits {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        input
+            .split(';')
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+            .map(|part| {
+                let (category, amount) = part
+                    .split_once('=')
+                    .ok_or_else(|| format!("expected category=amount, got '{}'", part))?;
+                let category = category.trim();
+                if category.is_empty() {
+                    return Err(format!("missing category in '{}'", part));
+                }
+                // ';' separates the splits, and '=' the category from the amount
+                if amount.contains('=') {
+                    return Err(format!(
+                        "a category can't contain '=' or ';', in '{}'",
+                        part
+                    ));
+                }
+                Ok(Split {
+                    category: category.to_string(),
+                    amount: Money::parse(amount)?,
+                })
+            })
+            .collect::<Result<Vec<Split>, String>>()
+            .map(Self)
+    }
+}
+
+impl Display for Splits {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let parts: Vec<String> = self
+            .iter()
+            .map(|split| format!("{}={}", split.category, split.amount))
+            .collect();
+        write!(f, "{}", parts.join("; "))
+    }
+}
+
+impl Serialize for Splits {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Splits {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let splits = String::deserialize(deserializer)?;
+        splits.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_categories_and_amounts() {
+        let splits: Splits = "groceries=120.50; household = 30;".parse().unwrap();
+        assert_eq!(splits.to_string(), "groceries=120.50; household=30.00");
+    }
+
+    #[test]
+    fn rejects_separators_in_categories() {
+        assert!("a=b=5".parse::<Splits>().is_err());
+        assert!("=5".parse::<Splits>().is_err());
+        assert!("groceries".parse::<Splits>().is_err());
+    }
+}
@@ -0,0 +1,150 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt};
+
+pub const MAX_SUGGESTIONS: usize = 5;
+
+/** Which suggestions a column offers */
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum AutocompletePolicy {
+    /** Complete typed text, and prefill from rows with the same details */
+    #[default]
+    All,
+    /** Only complete typed text that is a prefix of a previous value */
+    Prefix,
+    /** Only prefill from rows with the same details, typed text is never completed */
+    SameDetails,
+    Off,
+}
+
+impl AutocompletePolicy {
+    /** The defaults per column - typed dates and amounts are never completed */
+    pub fn defaults() -> Vec<Self> {
+        vec![
+            Self::SameDetails,
+            Self::SameDetails,
+            Self::All,
+            Self::All,
+            Self::All,
+            Self::All,
+            Self::All,
+            Self::All,
+            // the parts of a receipt rarely repeat
+            Self::Off,
+            Self::All,
+            Self::Prefix,
+        ]
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Self::All => Self::Prefix,
+            Self::Prefix => Self::SameDetails,
+            Self::SameDetails => Self::Off,
+            Self::Off => Self::All,
+        }
+    }
+
+    pub fn completes_input(self) -> bool {
+        matches!(self, Self::All | Self::Prefix)
+    }
+
+    pub fn prefills_from_details(self) -> bool {
+        matches!(self, Self::All | Self::SameDetails)
+    }
+
+    fn allows(self, match_kind: MatchKind) -> bool {
+        match self {
+            Self::Prefix => match_kind == MatchKind::Prefix,
+            _ => true,
+        }
+    }
+}
+
+impl fmt::Display for AutocompletePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Self::All => "all",
+            Self::Prefix => "prefix only",
+            Self::SameDetails => "same details only",
+            Self::Off => "off",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/** How well a value matches the typed input, better matches rank first */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum MatchKind {
+    Substring,
+    PrefixIgnoreCase,
+    Prefix,
+}
+
+impl MatchKind {
+    fn new(value: &str, input: &str) -> Option<Self> {
+        if value.starts_with(input) {
+            Some(Self::Prefix)
+        } else if value.to_lowercase().starts_with(&input.to_lowercase()) {
+            Some(Self::PrefixIgnoreCase)
+        } else if value.to_lowercase().contains(&input.to_lowercase()) {
+            Some(Self::Substring)
+        } else {
+            None
+        }
+    }
+}
+
+struct Candidate {
+    match_kind: MatchKind,
+    count: usize,
+    last_position: usize,
+}
+
+/**
+ * Ranks the distinct values that match the input, first by how they match and then by how
+ * often and how recently they were used.
+ * `values` are given in table order, so later values are more recent.
+ * The policy may restrict which matches are allowed.
+ */
+pub fn rank_suggestions(
+    values: impl Iterator<Item = String>,
+    input: &str,
+    policy: AutocompletePolicy,
+) -> Vec<String> {
+    let mut candidates: HashMap<String, Candidate> = HashMap::new();
+    let mut values_len = 0;
+    for (position, value) in values.enumerate() {
+        values_len = position + 1;
+        if value.is_empty() || value == input {
+            continue;
+        }
+        let match_kind = MatchKind::new(&value, input).filter(|kind| policy.allows(*kind));
+        if let Some(match_kind) = match_kind {
+            let candidate = candidates.entry(value).or_insert(Candidate {
+                match_kind,
+                count: 0,
+                last_position: position,
+            });
+            candidate.count += 1;
+            candidate.last_position = position;
+        }
+    }
+    let score = |candidate: &Candidate| {
+        let frequency = (1.0 + candidate.count as f64).ln();
+        let recency = (candidate.last_position + 1) as f64 / values_len.max(1) as f64;
+        frequency + recency
+    };
+    let mut ranked: Vec<(String, Candidate)> = candidates.into_iter().collect();
+    ranked.sort_by(|(a_value, a), (b_value, b)| {
+        b.match_kind
+            .cmp(&a.match_kind)
+            .then(score(b).total_cmp(&score(a)))
+            .then(a_value.cmp(b_value))
+    });
+    ranked
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(value, _)| value)
+        .collect()
+}
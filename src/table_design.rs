@@ -29,9 +29,9 @@ pub fn add_design_to_table<'a>(
                 .border_type(BorderType::Double)
                 .border_style(Style::new().fg(colors.border_color)),
         );
-    return formatted_table
+    formatted_table
         .row_highlight_style(selected_row_style)
         .column_highlight_style(selected_col_style)
         .cell_highlight_style(selected_cell_style)
-        .highlight_symbol(Text::from(vec!["".into(), bar.into(), "".into()]));
+        .highlight_symbol(Text::from(vec!["".into(), bar.into(), "".into()]))
 }
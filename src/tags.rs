@@ -0,0 +1,63 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+/**
+ * Labels that group transactions across categories, written as `vacation-2026, japan`.
+ * Files keep them in the same text form, so they fit in a csv column.
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Tags(Vec<String>);
+
+impl Tags {
+    pub fn iter(&self) -> impl Iterator<Item = &String> {
+        self.0.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /** Whether every one of the other tags is one of these, ignoring case */
+    pub fn contains_all(&self, other: &Self) -> bool {
+        other
+            .iter()
+            .all(|tag| self.iter().any(|own| own.eq_ignore_ascii_case(tag)))
+    }
+}
+
+impl FromStr for Tags {
+    type Err = String;
+
+    /** Splits the tags by commas, dropping empty and repeated tags */
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let mut tags: Vec<String> = vec![];
+        for tag in input.split(',').map(str::trim) {
+            if !tag.is_empty() && !tags.iter().any(|other| other.eq_ignore_ascii_case(tag)) {
+                tags.push(tag.to_string());
+            }
+        }
+        Ok(Self(tags))
+    }
+}
+
+impl Display for Tags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0.join(", "))
+    }
+}
+
+impl Serialize for Tags {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Tags {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let tags = String::deserialize(deserializer)?;
+        tags.parse().map_err(serde::de::Error::custom)
+    }
+}
@@ -1,7 +1,7 @@
 use color_eyre::Result;
 use core::fmt;
 use csv::{ReaderBuilder, WriterBuilder};
-use eyre::bail;
+use eyre::{bail, eyre, WrapErr};
 use itertools::Itertools;
 use ratatui::{
     text::Text,
@@ -12,11 +12,27 @@ use std::{
     cmp::Ordering,
     collections::{HashMap, HashSet},
     fmt::Display,
-    fs,
-    path::PathBuf,
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
     str::FromStr,
 };
-use time::{Date, Month};
+use time::{Date, Month, OffsetDateTime};
+
+use crate::{
+    accounts::{Accounts, AccountsSummary},
+    budgets::{BudgetPeriod, BudgetProgress, Budgets},
+    duplicates::find_duplicate,
+    exchange_rates::ExchangeRates,
+    history::{Edit, History, WHOLE_TRANSACTION},
+    money::{minor_units, Money},
+    recurring::RecurringTransactions,
+    rules::{RuleChange, Rules},
+    schema::{self, CsvLayout, COLUMNS},
+    splits::Splits,
+    suggestions::{rank_suggestions, AutocompletePolicy},
+    tags::Tags,
+};
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub struct SimpleDate {
@@ -30,6 +46,24 @@ impl SimpleDate {
         // since date is always valid, unwrap is safe
         Date::from_calendar_date(self.year, Month::try_from(self.month).unwrap(), self.day).unwrap()
     }
+
+    /** The local date, or the UTC date when the local offset can't be found */
+    pub fn today() -> Self {
+        OffsetDateTime::now_local()
+            .unwrap_or_else(|_| OffsetDateTime::now_utc())
+            .date()
+            .into()
+    }
+}
+
+impl From<Date> for SimpleDate {
+    fn from(date: Date) -> Self {
+        Self {
+            year: date.year(),
+            month: date.month().into(),
+            day: date.day(),
+        }
+    }
 }
 
 impl TryFrom<&str> for SimpleDate {
@@ -93,7 +127,7 @@ impl<'de> Deserialize<'de> for SimpleDate {
 
 impl PartialOrd for SimpleDate {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.date().cmp(&other.date()))
+        Some(self.cmp(other))
     }
 }
 
@@ -103,6 +137,187 @@ impl Ord for SimpleDate {
     }
 }
 
+/** A date pattern made of `YYYY` (or `YY`), `MM` and `DD` with separators, e.g. `DD/MM/YYYY` */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DateFormat {
+    pattern: String,
+}
+
+impl Default for DateFormat {
+    fn default() -> Self {
+        Self {
+            pattern: "YYYY.MM.DD".to_string(),
+        }
+    }
+}
+
+impl DateFormat {
+    /** The order of the year, month and day in the pattern */
+    fn order(&self) -> Vec<char> {
+        self.pattern
+            .chars()
+            .filter(|c| matches!(c, 'Y' | 'M' | 'D'))
+            .dedup()
+            .collect()
+    }
+
+    pub fn format(&self, date: &SimpleDate) -> String {
+        self.pattern
+            .replace("YYYY", &format!("{:04}", date.year))
+            .replace("YY", &format!("{:02}", date.year % 100))
+            .replace("MM", &format!("{:02}", date.month))
+            .replace("DD", &format!("{:02}", date.day))
+    }
+
+    /** Parses a date in the order of the pattern, accepting any separator between the parts */
+    pub fn parse(&self, input: &str) -> Result<SimpleDate, String> {
+        let parts: Vec<&str> = input
+            .split(|c: char| !c.is_ascii_digit())
+            .filter(|part| !part.is_empty())
+            .collect();
+        if parts.len() != 3 {
+            return Err(format!("Expected a date like {}: {}", self.pattern, input));
+        }
+        let part_of = |component: char| {
+            self.order()
+                .iter()
+                .position(|c| *c == component)
+                .and_then(|i| parts.get(i))
+                .copied()
+                .unwrap_or_default()
+        };
+        SimpleDate::try_from(format!("{}.{}.{}", part_of('Y'), part_of('M'), part_of('D')).as_str())
+    }
+}
+
+impl Serialize for DateFormat {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.pattern)
+    }
+}
+
+impl<'de> Deserialize<'de> for DateFormat {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let pattern = String::deserialize(deserializer)?;
+        let date_format = DateFormat { pattern };
+        if date_format.order().len() == 3 && date_format.order().iter().all_unique() {
+            Ok(date_format)
+        } else {
+            Err(serde::de::Error::custom(format!(
+                "Invalid date format, expected YYYY, MM and DD: {}",
+                date_format.pattern
+            )))
+        }
+    }
+}
+
+/** Which way the money went - a transfer moves money between your own accounts */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    In,
+    #[default]
+    Out,
+    Transfer,
+}
+
+impl Direction {
+    /**
+     * Normalizes a direction from a file written before directions were validated.
+     * Returns the direction and whether the stored text was already normalized,
+     * or `None` if the text is not a known direction.
+     */
+    fn migrate(stored: &str) -> Option<(Self, bool)> {
+        let direction = match stored.trim().to_lowercase().as_str() {
+            "income" | "incoming" => Self::In,
+            "outcome" | "outgoing" | "expense" | "expenses" => Self::Out,
+            "transfers" => Self::Transfer,
+            other => other.parse().ok()?,
+        };
+        Some((direction, direction.to_string() == stored))
+    }
+}
+
+/**
+ * The directions changed while loading a file written before directions were validated.
+ * The file is not rewritten, the changes are saved with the next save.
+ */
+#[derive(Debug, Default)]
+pub struct DirectionMigration {
+    /** How many old spellings, like `income`, were normalized */
+    pub normalized: usize,
+    /** The transactions whose direction was not recognized, and were loaded as the default */
+    pub unknown: Vec<String>,
+}
+
+impl DirectionMigration {
+    /** Returns the direction to load instead of the stored text, if it has to change */
+    fn migrate(&mut self, stored: &str) -> Option<Direction> {
+        match Direction::migrate(stored) {
+            Some((_, true)) => None,
+            Some((direction, false)) => {
+                self.normalized += 1;
+                Some(direction)
+            }
+            None => Some(Direction::default()),
+        }
+    }
+
+    /** Keeps where the transaction is in the file, if its stored direction was not recognized */
+    fn note_unknown(&mut self, position: String, stored: &str, transaction: &Transaction) {
+        if Direction::migrate(stored).is_some() {
+            return;
+        }
+        self.unknown.push(format!(
+            "{} ({} {}): unknown direction '{}', loaded as {}",
+            position, transaction.date, transaction.details, stored, transaction.direction
+        ));
+    }
+}
+
+impl FromStr for Direction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "in" => Ok(Self::In),
+            "out" => Ok(Self::Out),
+            "transfer" => Ok(Self::Transfer),
+            _ => Err(format!(
+                "invalid direction '{}', expected in, out or transfer",
+                s
+            )),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Direction {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let direction = String::deserialize(deserializer)?;
+        direction.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let direction = match self {
+            Self::In => "in",
+            Self::Out => "out",
+            Self::Transfer => "transfer",
+        };
+        write!(f, "{}", direction)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum TransactionField {
     Date,
@@ -112,6 +327,10 @@ pub enum TransactionField {
     Method,
     Direction,
     Currency,
+    Destination,
+    Splits,
+    Tags,
+    Notes,
 }
 
 impl TransactionField {
@@ -124,6 +343,10 @@ impl TransactionField {
             Self::Method,
             Self::Direction,
             Self::Currency,
+            Self::Destination,
+            Self::Splits,
+            Self::Tags,
+            Self::Notes,
         ]
     }
 
@@ -136,12 +359,16 @@ impl TransactionField {
             4 => Some(Self::Method),
             5 => Some(Self::Direction),
             6 => Some(Self::Currency),
+            7 => Some(Self::Destination),
+            8 => Some(Self::Splits),
+            9 => Some(Self::Tags),
+            10 => Some(Self::Notes),
             _ => None,
         }
     }
 
     pub fn widths() -> Vec<u16> {
-        vec![11, 10, 100, 15, 9, 9, 9]
+        vec![11, 10, 100, 15, 9, 9, 9, 9, 30, 20, 30]
     }
 
     pub fn names() -> Vec<String> {
@@ -153,6 +380,10 @@ impl TransactionField {
             "Method",
             "Direction",
             "Currency",
+            "To",
+            "Splits",
+            "Tags",
+            "Notes",
         ]
         .into_iter()
         .map(|s| s.to_string())
@@ -164,71 +395,285 @@ impl TransactionField {
 #[serde(rename_all = "camelCase")]
 pub struct Transaction {
     pub date: SimpleDate,
-    amount: f64,
+    amount: Money,
+    #[serde(default)]
     pub details: String,
+    #[serde(default)]
     pub category: String,
+    #[serde(default)]
     method: String,
-    pub direction: String,
+    pub direction: Direction,
+    #[serde(default)]
     currency: String,
+    /** The account a transfer moves the money to, from the `method` account */
+    #[serde(default)]
+    destination: String,
+    /** The parts of the amount by category, the category is ignored when there are splits */
+    #[serde(default)]
+    splits: Splits,
+    #[serde(default)]
+    tags: Tags,
+    /** A free-form memo, unlike the details it is not used to autofill */
+    #[serde(default)]
+    notes: String,
+    /** Added in this session (typed or imported), so it is checked for duplicates */
+    #[serde(skip)]
+    pub is_new: bool,
+    /** Added from a recurring transaction, highlighted until it is edited or kept */
+    #[serde(skip)]
+    pub needs_review: bool,
+    /** The values of the csv columns the schema doesn't know, written back as they were */
+    #[serde(skip)]
+    extra: Vec<String>,
 }
 
 impl Transaction {
+    pub fn new(
+        date: SimpleDate,
+        amount: Money,
+        details: String,
+        category: String,
+        method: String,
+        direction: Direction,
+        currency: String,
+    ) -> Self {
+        Self {
+            date,
+            amount,
+            details,
+            category,
+            method,
+            direction,
+            currency,
+            destination: "".to_string(),
+            splits: Splits::default(),
+            tags: Tags::default(),
+            notes: "".to_string(),
+            is_new: false,
+            needs_review: false,
+            extra: vec![],
+        }
+    }
+
+    /**
+     * How the transaction changes the balance of each account it touches - a transfer takes the
+     * amount from the method account and adds it to the destination account
+     */
+    pub fn account_changes(&self) -> Vec<(&str, Money)> {
+        let changes = match self.direction {
+            Direction::In => vec![(self.method.as_str(), self.amount)],
+            Direction::Out => vec![(self.method.as_str(), -self.amount)],
+            Direction::Transfer => vec![
+                (self.method.as_str(), -self.amount),
+                (self.destination.as_str(), self.amount),
+            ],
+        };
+        changes
+            .into_iter()
+            .filter(|(account, _)| !account.is_empty())
+            .collect()
+    }
+
+    /** The categories the amount is counted in - each split, or the whole amount in the category */
+    pub fn allocations(&self) -> Vec<(&str, Money)> {
+        match self.splits.is_empty() {
+            true => vec![(self.category.as_str(), self.amount)],
+            false => self
+                .splits
+                .iter()
+                .map(|split| (split.category.as_str(), split.amount))
+                .collect(),
+        }
+    }
+
+    pub fn currency(&self) -> &str {
+        &self.currency
+    }
+
+    pub fn same_amount(&self, other: &Self) -> bool {
+        self.amount == other.amount && self.currency == other.currency
+    }
+
+    /**
+     * Sets the given fields, leaving the fields that are `None` unchanged.
+     * A currency the amount doesn't fit is not set, and the error says why.
+     */
+    pub fn set_fields(
+        &mut self,
+        category: Option<&str>,
+        method: Option<&str>,
+        direction: Option<Direction>,
+        currency: Option<&str>,
+    ) -> Result<(), String> {
+        let fields = [(&mut self.category, category), (&mut self.method, method)];
+        for (field, value) in fields {
+            if let Some(value) = value {
+                *field = value.to_string();
+            }
+        }
+        if let Some(direction) = direction {
+            self.direction = direction;
+        }
+        match currency {
+            Some(currency) => self.set_currency(currency),
+            None => Ok(()),
+        }
+    }
+
+    /** Changes the currency, unless the amount or the splits have more decimal digits than it */
+    pub fn set_currency(&mut self, currency: &str) -> Result<(), String> {
+        let before = std::mem::replace(&mut self.currency, currency.to_string());
+        self.check_currency_digits()
+            .inspect_err(|_| self.currency = before)
+    }
+
+    /** Describes the fields that differ in the other transaction, e.g. `category: '' → 'food'` */
+    pub fn changed_fields(&self, other: &Self) -> Vec<String> {
+        let date_format = DateFormat::default();
+        TransactionField::all_fields()
+            .iter()
+            .zip(TransactionField::names())
+            .filter_map(|(field, name)| {
+                let before = self.get_field_text(field, &date_format);
+                let after = other.get_field_text(field, &date_format);
+                (before != after).then(|| format!("{}: '{}' → '{}'", name, before, after))
+            })
+            .collect()
+    }
+
+    /** Unlike `==`, compares every field including the direction */
+    pub fn same_fields(&self, other: &Self) -> bool {
+        self.changed_fields(other).is_empty()
+    }
+
+    /** Checks that the amount and the splits have no more decimal digits than the currency */
+    pub fn check_currency_digits(&self) -> Result<(), String> {
+        let decimals = minor_units(&self.currency);
+        if self.amount.round_to(decimals) != self.amount {
+            return Err(format!(
+                "{} has {} decimal digits, the amount {} has more",
+                self.currency, decimals, self.amount
+            ));
+        }
+        self.splits.validate(self.amount, &self.currency)
+    }
+
+    /** Fills the empty fields of this transaction from the other one */
+    pub fn merge_from(&mut self, other: &Self) {
+        let fields = [
+            (&mut self.category, &other.category),
+            (&mut self.method, &other.method),
+            (&mut self.currency, &other.currency),
+            (&mut self.destination, &other.destination),
+            (&mut self.notes, &other.notes),
+        ];
+        for (field, other_field) in fields {
+            if field.is_empty() {
+                *field = other_field.clone();
+            }
+        }
+        if self.tags.is_empty() {
+            self.tags = other.tags.clone();
+        }
+        // the other's splits are kept only if they add up to this amount
+        if self.splits.is_empty() && other.splits.validate(self.amount, &self.currency).is_ok() {
+            self.splits = other.splits.clone();
+        }
+    }
+
     fn mutate_field_by_transaction_field(
         &mut self,
         field: TransactionField,
         input: &str,
+        date_format: &DateFormat,
     ) -> Result<(), String> {
         match field {
-            TransactionField::Date => match SimpleDate::try_from(input) {
+            TransactionField::Date => match date_format.parse(input) {
                 Ok(date) => self.date = date,
                 Err(e) => return Err(format!(" failed to parse as date: {}", e)),
             },
-            TransactionField::Amount => match f64::from_str(input) {
-                Ok(num) => self.amount = num,
-                Err(e) => return Err(format!(" failed to parse as number: {}", e)),
+            TransactionField::Amount => match Money::parse(input) {
+                Ok(amount) if amount.round_to(minor_units(&self.currency)) != amount => {
+                    return Err(format!(
+                        " {} has {} decimal digits",
+                        self.currency,
+                        minor_units(&self.currency)
+                    ))
+                }
+                Ok(amount) => {
+                    self.splits
+                        .validate(amount, &self.currency)
+                        .map_err(|e| format!(" {}, change the splits first", e))?;
+                    self.amount = amount
+                }
+                Err(e) => return Err(format!(" {}", e)),
             },
             TransactionField::Details => self.details = input.to_string(),
             TransactionField::Category => self.category = input.to_string(),
             TransactionField::Method => self.method = input.to_string(),
-            TransactionField::Direction => self.direction = input.to_string(),
-            TransactionField::Currency => self.currency = input.to_string(),
+            TransactionField::Direction => match Direction::from_str(input) {
+                Ok(direction) => self.direction = direction,
+                Err(e) => return Err(format!(" {}", e)),
+            },
+            TransactionField::Currency => {
+                self.set_currency(input).map_err(|e| format!(" {}", e))?
+            }
+            TransactionField::Destination => self.destination = input.to_string(),
+            TransactionField::Splits => {
+                let splits = Splits::from_str(input).map_err(|e| format!(" {}", e))?;
+                splits
+                    .validate(self.amount, &self.currency)
+                    .map_err(|e| format!(" {}", e))?;
+                self.splits = splits;
+            }
+            TransactionField::Tags => self.tags = Tags::from_str(input)?,
+            TransactionField::Notes => self.notes = input.to_string(),
         }
         Ok(())
     }
 
-    pub fn mutate_field(&mut self, field_index: usize, input: &str) -> Result<(), String> {
+    pub fn mutate_field(
+        &mut self,
+        field_index: usize,
+        input: &str,
+        date_format: &DateFormat,
+    ) -> Result<(), String> {
         match TransactionField::get(field_index) {
-            Some(field) => self.mutate_field_by_transaction_field(field, input),
+            Some(field) => self.mutate_field_by_transaction_field(field, input, date_format),
             None => Ok(()),
         }
     }
 
-    fn get_field_text(&self, field: &TransactionField) -> String {
+    fn get_field_text(&self, field: &TransactionField, date_format: &DateFormat) -> String {
         match field {
-            TransactionField::Date => format!("{}", self.date),
+            TransactionField::Date => date_format.format(&self.date),
             TransactionField::Amount => {
-                if self.amount == 0.0 {
+                if self.amount.is_zero() {
                     "".to_string()
                 } else {
-                    format!("{:.2}", self.amount)
+                    self.amount.format_in(&self.currency)
                 }
             }
             TransactionField::Details => self.details.clone(),
             TransactionField::Category => self.category.clone(),
             TransactionField::Method => self.method.clone(),
-            TransactionField::Direction => self.direction.clone(),
+            TransactionField::Direction => self.direction.to_string(),
             TransactionField::Currency => self.currency.clone(),
+            TransactionField::Destination => self.destination.clone(),
+            TransactionField::Splits => self.splits.format_in(&self.currency),
+            TransactionField::Tags => self.tags.to_string(),
+            TransactionField::Notes => self.notes.clone(),
         }
     }
 
-    pub fn get_column_text(&self, field_index: usize) -> Option<String> {
-        TransactionField::get(field_index).map(|field| self.get_field_text(&field))
+    pub fn get_column_text(&self, field_index: usize, date_format: &DateFormat) -> Option<String> {
+        TransactionField::get(field_index).map(|field| self.get_field_text(&field, date_format))
     }
 
-    pub fn generate_row(&self) -> Row<'_> {
+    pub fn generate_row(&self, date_format: &DateFormat) -> Row<'_> {
         let cells: Vec<Cell> = TransactionField::all_fields()
             .into_iter()
-            .map(|field| self.get_field_text(&field))
+            .map(|field| self.get_field_text(&field, date_format))
             .map(|text| Cell::from(Text::from(format!("\n{}\n", text))))
             .collect();
         Row::new(cells)
@@ -238,7 +683,7 @@ impl Transaction {
 impl PartialEq for Transaction {
     fn eq(&self, other: &Self) -> bool {
         let date_cmp = self.date == other.date;
-        let amount_cmp = (self.amount - other.amount).abs() < 1e-6;
+        let amount_cmp = self.amount == other.amount;
         let currency_cmp = self.currency == other.currency;
         let details_cmp = self.details == other.details;
         let category_cmp = self.category == other.category;
@@ -251,7 +696,7 @@ impl Eq for Transaction {}
 
 impl PartialOrd for Transaction {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.date.cmp(&other.date))
+        Some(self.cmp(other))
     }
 }
 
@@ -268,8 +713,8 @@ pub enum FileType {
 }
 
 impl FileType {
-    pub fn new(file_path: &PathBuf) -> FileType {
-        let extension = file_path.extension().map(|ext| ext.to_str()).flatten();
+    pub fn new(file_path: &Path) -> FileType {
+        let extension = file_path.extension().and_then(|ext| ext.to_str());
         match extension {
             Some("json") => FileType::Json,
             Some("csv") => FileType::Csv,
@@ -284,189 +729,983 @@ pub type MonthInYear = (
     u8,  //month
 );
 
+/** How many months each report row sums */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Granularity {
+    #[default]
+    Month,
+    Quarter,
+    Year,
+}
+
+impl Granularity {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Month => Self::Quarter,
+            Self::Quarter => Self::Year,
+            Self::Year => Self::Month,
+        }
+    }
+}
+
+/** What the report groups the transactions of each direction by */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Grouping {
+    #[default]
+    Category,
+    /** A transaction is counted in each of its tags, untagged transactions only in the direction */
+    Tag,
+}
+
+impl Grouping {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Category => Self::Tag,
+            Self::Tag => Self::Category,
+        }
+    }
+}
+
+impl Display for Grouping {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Category => write!(f, "Category"),
+            Self::Tag => write!(f, "Tag"),
+        }
+    }
+}
+
+/** A report row - a month, a quarter or a year */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Period {
+    Month(MonthInYear),
+    Quarter(i32, u8),
+    Year(i32),
+}
+
+impl Period {
+    pub fn new(month: &MonthInYear, granularity: Granularity) -> Self {
+        match granularity {
+            Granularity::Month => Self::Month(*month),
+            Granularity::Quarter => Self::Quarter(month.0, quarter_of(month.1)),
+            Granularity::Year => Self::Year(month.0),
+        }
+    }
+
+    pub fn year(&self) -> i32 {
+        match self {
+            Self::Month((year, _)) | Self::Quarter(year, _) | Self::Year(year) => *year,
+        }
+    }
+
+    /** The first and last month of the period */
+    fn month_range(&self) -> (u8, u8) {
+        match self {
+            Self::Month((_, month)) => (*month, *month),
+            Self::Quarter(_, quarter) => (quarter * 3 - 2, quarter * 3),
+            Self::Year(_) => (1, 12),
+        }
+    }
+
+    pub fn contains(&self, month: &MonthInYear) -> bool {
+        let (first, last) = self.month_range();
+        month.0 == self.year() && (first..=last).contains(&month.1)
+    }
+
+    pub fn previous(&self) -> Self {
+        match *self {
+            Self::Month((year, 1)) => Self::Month((year - 1, 12)),
+            Self::Month((year, month)) => Self::Month((year, month - 1)),
+            Self::Quarter(year, 1) => Self::Quarter(year - 1, 4),
+            Self::Quarter(year, quarter) => Self::Quarter(year, quarter - 1),
+            Self::Year(year) => Self::Year(year - 1),
+        }
+    }
+
+    /** The same period a year before, none for a year, as that is already its previous period */
+    pub fn last_year(&self) -> Option<Self> {
+        match *self {
+            Self::Month((year, month)) => Some(Self::Month((year - 1, month))),
+            Self::Quarter(year, quarter) => Some(Self::Quarter(year - 1, quarter)),
+            Self::Year(_) => None,
+        }
+    }
+
+    /** Whether the month is in the period's year, up to the end of the period */
+    pub fn year_to_date_contains(&self, month: &MonthInYear) -> bool {
+        month.0 == self.year() && month.1 <= self.month_range().1
+    }
+}
+
+impl Display for Period {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Month((year, month)) => write!(f, "{:04}.{:02}", year, month),
+            Self::Quarter(year, quarter) => write!(f, "{:04} Q{}", year, quarter),
+            Self::Year(year) => write!(f, "{:04}", year),
+        }
+    }
+}
+
+fn quarter_of(month: u8) -> u8 {
+    month.div_ceil(3)
+}
+
 pub type DirectionAndCategory = (
-    Option<String>, // direction
-    Option<String>, // category
+    Option<Direction>, // direction
+    Option<String>,    // category
 );
-type SummaryMap = HashMap<(DirectionAndCategory, MonthInYear), f64>;
+/** Which directions are income and which are expenses, for the net cash flow */
+#[derive(Debug, Clone)]
+pub struct CashFlow {
+    pub income: Vec<Direction>,
+    pub expense: Vec<Direction>,
+}
+
+impl CashFlow {
+    fn label(&self) -> String {
+        let join = |directions: &[Direction]| directions.iter().map(Direction::to_string).join("+");
+        format!("{} - {}", join(&self.income), join(&self.expense))
+    }
+}
+
+/** A report column - the transactions in their original currency, or all of them converted to the home currency */
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ReportCurrency {
+    Home(String),
+    Original(String),
+}
+
+impl ReportCurrency {
+    /** The currency to filter the transactions by, the home column includes every currency */
+    pub fn filter_currency(&self) -> Option<String> {
+        match self {
+            Self::Home(_) => None,
+            Self::Original(currency) => Some(currency.clone()),
+        }
+    }
+
+    pub fn code(&self) -> &str {
+        match self {
+            Self::Home(currency) | Self::Original(currency) => currency,
+        }
+    }
+}
 
-const DEFAULT_CURRENCY: &str = "ILS";
+impl Display for ReportCurrency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Home(currency) => write!(f, "→{}", currency),
+            Self::Original(currency) => write!(f, "{}", currency),
+        }
+    }
+}
+
+type SummaryMap = HashMap<(DirectionAndCategory, MonthInYear, ReportCurrency), Money>;
+type MissingRatesMap = HashMap<(DirectionAndCategory, MonthInYear), usize>;
+type AccountSummaryMap = HashMap<(String, MonthInYear, ReportCurrency), Money>;
+type AccountMissingRatesMap = HashMap<(String, MonthInYear), usize>;
+
+pub const DEFAULT_CURRENCY: &str = "ILS";
 
 pub struct TransactionsReport {
     pub months: Vec<MonthInYear>,
+    /** The report rows, grouping the months by the granularity */
+    periods: Vec<Period>,
+    pub currencies: Vec<ReportCurrency>,
     categories: Vec<DirectionAndCategory>,
     category_summary: SummaryMap,
+    /** Number of transactions that could not be converted to the home currency */
+    missing_rates: MissingRatesMap,
+    accounts: Vec<String>,
+    /** How much each account changed in each month, transfers included */
+    account_summary: AccountSummaryMap,
+    /** Number of account changes that could not be converted to the home currency */
+    account_missing_rates: AccountMissingRatesMap,
 }
 
 impl TransactionsReport {
-    pub fn new(transactions: &Vec<Transaction>) -> Self {
+    pub fn new(
+        transactions: &[Transaction],
+        home_currency: &str,
+        exchange_rates: &ExchangeRates,
+        grouping: Grouping,
+    ) -> Self {
         let mut months: HashSet<MonthInYear> = HashSet::new();
+        let mut currencies: HashSet<ReportCurrency> = HashSet::new();
         let mut categories: HashSet<DirectionAndCategory> = HashSet::new();
         let mut category_summary: SummaryMap = HashMap::default();
+        let mut missing_rates: MissingRatesMap = HashMap::default();
+        let mut accounts: HashSet<String> = HashSet::new();
+        let mut account_summary: AccountSummaryMap = HashMap::default();
+        let mut account_missing_rates: AccountMissingRatesMap = HashMap::default();
         transactions.iter().for_each(|transaction| {
-            if transaction.currency == DEFAULT_CURRENCY {
-                let month_in_year = (transaction.date.year, transaction.date.month);
-                months.insert(month_in_year);
-                categories.insert((
-                    Some(transaction.direction.clone()),
-                    Some(transaction.category.clone()),
-                ));
-                categories.insert((Some(transaction.direction.clone()), None));
-                *category_summary
+            let month_in_year = (transaction.date.year, transaction.date.month);
+            let original_currency = ReportCurrency::Original(transaction.currency.clone());
+            let home = ReportCurrency::Home(home_currency.to_string());
+            let convert = |amount: Money| {
+                exchange_rates.convert(
+                    amount,
+                    &transaction.currency,
+                    home_currency,
+                    &transaction.date,
+                )
+            };
+            months.insert(month_in_year);
+            currencies.insert(original_currency.clone());
+            currencies.insert(home.clone());
+            for (account, change) in transaction.account_changes() {
+                accounts.insert(account.to_string());
+                *account_summary
                     .entry((
-                        (
-                            Some(transaction.direction.clone()),
-                            Some(transaction.category.clone()),
-                        ),
+                        account.to_string(),
                         month_in_year,
+                        original_currency.clone(),
                     ))
-                    .or_insert(0.0) += transaction.amount;
+                    .or_default() += change;
+                match convert(change) {
+                    Some(converted) => {
+                        *account_summary
+                            .entry((account.to_string(), month_in_year, home.clone()))
+                            .or_default() += converted
+                    }
+                    None => {
+                        *account_missing_rates
+                            .entry((account.to_string(), month_in_year))
+                            .or_insert(0) += 1
+                    }
+                }
+            }
+            let mut add = |direction_and_category: DirectionAndCategory,
+                           amount: Money,
+                           converted_amount: Option<Money>| {
+                categories.insert(direction_and_category.clone());
                 *category_summary
-                    .entry(((Some(transaction.direction.clone()), None), month_in_year))
-                    .or_insert(0.0) += transaction.amount;
+                    .entry((
+                        direction_and_category.clone(),
+                        month_in_year,
+                        original_currency.clone(),
+                    ))
+                    .or_default() += amount;
+                let home_sum = category_summary
+                    .entry((direction_and_category.clone(), month_in_year, home.clone()))
+                    .or_default();
+                match converted_amount {
+                    Some(amount) => *home_sum += amount,
+                    None => {
+                        *missing_rates
+                            .entry((direction_and_category, month_in_year))
+                            .or_insert(0) += 1
+                    }
+                }
+            };
+            let direction = Some(transaction.direction);
+            // each split is converted on its own, so the direction total is the sum of its parts
+            let mut converted_total = Some(Money::default());
+            for (category, amount) in transaction.allocations() {
+                let converted_amount = convert(amount);
+                converted_total = converted_total
+                    .zip(converted_amount)
+                    .map(|(total, converted)| total + converted);
+                if grouping == Grouping::Category {
+                    add(
+                        (direction, Some(category.to_string())),
+                        amount,
+                        converted_amount,
+                    );
+                }
             }
+            if grouping == Grouping::Tag {
+                for tag in transaction.tags.iter() {
+                    add(
+                        (direction, Some(tag.clone())),
+                        transaction.amount,
+                        convert(transaction.amount),
+                    );
+                }
+            }
+            add((direction, None), transaction.amount, converted_total);
         });
         let months: Vec<MonthInYear> = months.into_iter().sorted().rev().collect();
+        let currencies: Vec<ReportCurrency> = currencies.into_iter().sorted().collect();
         let categories: Vec<DirectionAndCategory> = categories.into_iter().sorted().collect();
+        let accounts: Vec<String> = accounts.into_iter().sorted().collect();
+        let periods = months.iter().map(|month| Period::Month(*month)).collect();
         TransactionsReport {
             months,
+            periods,
+            currencies,
             categories,
             category_summary,
+            missing_rates,
+            accounts,
+            account_summary,
+            account_missing_rates,
         }
     }
 
     pub fn rows_len(&self) -> usize {
-        self.months.len()
+        self.periods.len()
+    }
+
+    /** Groups the report rows by months, quarters or years */
+    pub fn set_granularity(&mut self, granularity: Granularity) {
+        self.periods = self
+            .months
+            .iter()
+            .map(|month| Period::new(month, granularity))
+            .dedup()
+            .collect();
+    }
+
+    /** Returns the home currency totals, or the first currency if there are no transactions */
+    pub fn default_currency(&self) -> Option<ReportCurrency> {
+        self.currencies
+            .iter()
+            .find(|currency| matches!(currency, ReportCurrency::Home(_)))
+            .or(self.currencies.first())
+            .cloned()
+    }
+
+    fn get_sum(
+        &self,
+        direction_and_category: &DirectionAndCategory,
+        month: &MonthInYear,
+        currency: &ReportCurrency,
+    ) -> Option<Money> {
+        self.category_summary
+            .get(&(direction_and_category.clone(), *month, currency.clone()))
+            .copied()
+    }
+
+    /** Returns the sum of the months in the period that have transactions */
+    fn get_period_sum(
+        &self,
+        direction_and_category: &DirectionAndCategory,
+        period: &Period,
+        currency: &ReportCurrency,
+    ) -> Option<Money> {
+        self.months
+            .iter()
+            .filter(|month| period.contains(month))
+            .filter_map(|month| self.get_sum(direction_and_category, month, currency))
+            .reduce(|acc, month_val| acc + month_val)
     }
 
-    pub fn get_month_rows(
+    /** Returns the number of transactions without an exchange rate, for one period or for all months */
+    fn get_missing_rates(
         &self,
         direction_and_category: &DirectionAndCategory,
-    ) -> Vec<(String, f64)> {
+        period: Option<&Period>,
+    ) -> usize {
         self.months
             .iter()
-            .map(|month| {
-                let month_str = format!("\n{:04}.{:02}", month.0, month.1);
-                let category_amount: f64 = *self
-                    .category_summary
+            .filter(|month| period.is_none_or(|period| period.contains(month)))
+            .filter_map(|month| {
+                self.missing_rates
                     .get(&(direction_and_category.clone(), *month))
-                    .unwrap_or(&0.0);
-                (month_str, category_amount)
             })
-            .collect()
+            .sum()
     }
 
-    pub fn get_month_at_index(&self, index: Option<usize>) -> Option<&MonthInYear> {
-        index.map(|index| self.months.get(index)).flatten()
+    /** Returns the total number of transactions that could not be converted to the home currency */
+    pub fn missing_rates_count(&self) -> usize {
+        self.missing_rates
+            .iter()
+            .filter(|(((_, category), _), _)| category.is_none())
+            .map(|(_, count)| count)
+            .sum()
     }
 
-    /** Returns a vector of categories that have a non-0 value for the given month */
-    pub fn get_categories_for_month_by_index(
-        &self,
-        index: Option<usize>,
-    ) -> Vec<DirectionAndCategory> {
-        if let Some(month) = self.get_month_at_index(index) {
-            self.categories
-                .iter()
-                .filter(|direction_and_category| {
-                    self.category_summary
-                        .get(&((*direction_and_category).clone(), *month))
-                        .map_or(false, |amount| amount != &0.0)
-                })
-                .map(|dac| dac.clone())
-                .collect()
-        } else {
-            self.categories.clone()
+    /** Flags a home currency value that leaves out transactions without an exchange rate, e.g. `120.00 !2` */
+    fn flag_missing_rates(text: String, currency: &ReportCurrency, missing_rates: usize) -> String {
+        match currency {
+            ReportCurrency::Home(_) if missing_rates > 0 => {
+                format!("{} !{}", text, missing_rates)
+            }
+            _ => text,
         }
     }
 
-    /** Returns the category label for a month index and a category index */
-    pub fn get_category_by_index_for_month_at_index(
+    /** Formats the sums, flagging home currency sums that are missing exchange rates with a `!` */
+    fn format_sums(
         &self,
-        month_index: Option<usize>,
-        category_index: Option<usize>,
-    ) -> DirectionAndCategory {
-        let categories = self.get_categories_for_month_by_index(month_index);
-        category_index.map_or((None, None), |category_index| {
-            categories
-                .get(category_index)
-                .map_or((None, None), |category| category.clone())
-        })
+        direction_and_category: &DirectionAndCategory,
+        period: Option<&Period>,
+        currencies: &[ReportCurrency],
+    ) -> Vec<String> {
+        let missing_rates = self.get_missing_rates(direction_and_category, period);
+        self.get_sums(direction_and_category, period, currencies)
+            .into_iter()
+            .zip(currencies)
+            .map(|(sum, currency)| {
+                let text = sum.unwrap_or_default().format_in(currency.code());
+                Self::flag_missing_rates(text, currency, missing_rates)
+            })
+            .collect()
     }
 
-    pub fn get_category_rows_for_month_by_index(&self, index: Option<usize>) -> Vec<Vec<String>> {
-        let month = self.get_month_at_index(index);
-        self.categories
+    /** Returns the sum of a category in every given currency, for one period or for all months */
+    fn get_sums(
+        &self,
+        direction_and_category: &DirectionAndCategory,
+        period: Option<&Period>,
+        currencies: &[ReportCurrency],
+    ) -> Vec<Option<Money>> {
+        currencies
             .iter()
-            .map(|direction_and_category| match month {
-                Some(&month) => self
-                    .category_summary
-                    .get(&(direction_and_category.clone(), month))
-                    .map(|sum| (direction_and_category, *sum)),
-                None => {
-                    let sum = self.months.iter().fold(0.0, |acc, &month| {
-                        self.category_summary
-                            .get(&(direction_and_category.clone(), month))
-                            .map_or(acc, |month_val| month_val + acc)
-                    });
-                    Some((direction_and_category, sum))
-                }
-            })
-            .filter_map(|category_sum| category_sum)
-            .map(|(direction_and_category, sum)| {
-                vec![
-                    format!(
-                        "\n{} - {}",
-                        direction_and_category
-                            .0
-                            .as_ref()
-                            .unwrap_or(&"*".to_string()),
-                        direction_and_category
-                            .1
-                            .as_ref()
-                            .unwrap_or(&"*".to_string())
-                    ),
-                    format!("\n{:02.2}\n", sum),
-                ]
+            .map(|currency| match period {
+                Some(period) => self.get_period_sum(direction_and_category, period, currency),
+                None => self
+                    .months
+                    .iter()
+                    .filter_map(|month| self.get_sum(direction_and_category, month, currency))
+                    .reduce(|acc, month_val| acc + month_val),
             })
             .collect()
     }
-}
 
-#[derive(Default, Clone)]
+    /** Returns the percent of a category out of its direction's total, for one period or for all months */
+    fn get_share(
+        &self,
+        direction_and_category: &DirectionAndCategory,
+        period: Option<&Period>,
+        currency: &ReportCurrency,
+    ) -> Option<f64> {
+        let currencies = std::slice::from_ref(currency);
+        let direction_total = (direction_and_category.0, None);
+        let sum = self.get_sums(direction_and_category, period, currencies)[0]?;
+        let total = self.get_sums(&direction_total, period, currencies)[0]?;
+        (!total.is_zero()).then(|| 100.0 * sum.to_f64() / total.to_f64())
+    }
+
+    /**
+     * Returns how much a category's sum changed from an earlier period, absolute and in percent.
+     * There is no change if the report has no transactions in the earlier period.
+     */
+    fn get_change(
+        &self,
+        direction_and_category: &DirectionAndCategory,
+        period: &Period,
+        earlier: &Period,
+        currency: &ReportCurrency,
+    ) -> Option<(Money, Option<f64>)> {
+        if !self.months.iter().any(|month| earlier.contains(month)) {
+            return None;
+        }
+        let sum = self
+            .get_period_sum(direction_and_category, period, currency)
+            .unwrap_or_default();
+        let earlier_sum = self
+            .get_period_sum(direction_and_category, earlier, currency)
+            .unwrap_or_default();
+        let change = sum - earlier_sum;
+        let percent =
+            (!earlier_sum.is_zero()).then(|| 100.0 * change.to_f64() / earlier_sum.abs().to_f64());
+        Some((change, percent))
+    }
+
+    fn format_change(change: Option<(Money, Option<f64>)>, currency: &ReportCurrency) -> String {
+        let format_signed = |change: Money| {
+            let sign = if change.is_negative() { "" } else { "+" };
+            format!("{}{}", sign, change.format_in(currency.code()))
+        };
+        match change {
+            Some((change, Some(percent))) => {
+                format!("\n{}\n{:+.0}%", format_signed(change), percent)
+            }
+            Some((change, None)) => format!("\n{}\nnew", format_signed(change)),
+            None => "".to_string(),
+        }
+    }
+
+    fn format_share(share: Option<f64>) -> String {
+        share.map_or("".to_string(), |share| format!("{:.0}%", share))
+    }
+
+    /**
+     * Returns the income minus the expenses, and the percent of the income that is left, for one
+     * period or for all months. There is no cash flow if there is no income and no expenses.
+     */
+    fn get_cash_flow(
+        &self,
+        period: Option<&Period>,
+        currency: &ReportCurrency,
+        cash_flow: &CashFlow,
+    ) -> Option<(Money, Option<f64>)> {
+        let total = |directions: &[Direction]| {
+            self.categories
+                .iter()
+                .filter(|(direction, category)| {
+                    category.is_none()
+                        && direction.is_some_and(|direction| directions.contains(&direction))
+                })
+                .filter_map(|direction_total| {
+                    self.get_sums(direction_total, period, std::slice::from_ref(currency))[0]
+                })
+                .reduce(|acc, sum| acc + sum)
+        };
+        let (income, expense) = match (total(&cash_flow.income), total(&cash_flow.expense)) {
+            (None, None) => return None,
+            (income, expense) => (income.unwrap_or_default(), expense.unwrap_or_default()),
+        };
+        let net = income - expense;
+        Some((
+            net,
+            (!income.is_zero()).then(|| 100.0 * net.to_f64() / income.to_f64()),
+        ))
+    }
+
+    /** Returns the number of income and expense transactions without an exchange rate */
+    fn get_cash_flow_missing_rates(&self, period: Option<&Period>, cash_flow: &CashFlow) -> usize {
+        cash_flow
+            .income
+            .iter()
+            .chain(&cash_flow.expense)
+            .unique()
+            .map(|direction| self.get_missing_rates(&(Some(*direction), None), period))
+            .sum()
+    }
+
+    /** Formats the net and the savings rate, both flagged when they are missing exchange rates */
+    fn format_cash_flow(
+        &self,
+        period: Option<&Period>,
+        currency: &ReportCurrency,
+        cash_flow: &CashFlow,
+    ) -> (String, String) {
+        let missing_rates = self.get_cash_flow_missing_rates(period, cash_flow);
+        match self.get_cash_flow(period, currency, cash_flow) {
+            Some((net, savings_rate)) => (
+                Self::flag_missing_rates(net.format_in(currency.code()), currency, missing_rates),
+                savings_rate.map_or("".to_string(), |rate| {
+                    Self::flag_missing_rates(format!("{:.0}%", rate), currency, missing_rates)
+                }),
+            ),
+            None => ("".to_string(), "".to_string()),
+        }
+    }
+
+    /** Returns a net cash flow row and a savings rate row, for the period at the index */
+    pub fn get_cash_flow_rows(
+        &self,
+        index: Option<usize>,
+        currencies: &[ReportCurrency],
+        cash_flow: &CashFlow,
+    ) -> Vec<Vec<String>> {
+        let period = self.get_period_at_index(index);
+        let cash_flows: Vec<Option<(Money, Option<f64>)>> = currencies
+            .iter()
+            .map(|currency| self.get_cash_flow(period, currency, cash_flow))
+            .collect();
+        if cash_flows.iter().all(Option::is_none) {
+            return vec![];
+        }
+        let (nets, savings_rates): (Vec<String>, Vec<String>) = currencies
+            .iter()
+            .map(|currency| self.format_cash_flow(period, currency, cash_flow))
+            .map(|(net, savings_rate)| (format!("\n{}\n", net), format!("\n{}\n", savings_rate)))
+            .unzip();
+        vec![
+            std::iter::once(format!("\n= net ({})", cash_flow.label()))
+                .chain(nets)
+                .collect(),
+            std::iter::once("\n= savings rate".to_string())
+                .chain(savings_rates)
+                .collect(),
+        ]
+    }
+
+    /** Returns how much the account changed in every given currency, for one period or for all months */
+    fn get_account_sums(
+        &self,
+        account: &str,
+        period: Option<&Period>,
+        currencies: &[ReportCurrency],
+    ) -> Vec<Option<Money>> {
+        currencies
+            .iter()
+            .map(|currency| {
+                self.months
+                    .iter()
+                    .filter(|month| period.is_none_or(|period| period.contains(month)))
+                    .filter_map(|month| {
+                        self.account_summary
+                            .get(&(account.to_string(), *month, currency.clone()))
+                            .copied()
+                    })
+                    .reduce(|acc, sum| acc + sum)
+            })
+            .collect()
+    }
+
+    /** Returns the number of an account's changes without an exchange rate, for one period or for all months */
+    fn get_account_missing_rates(&self, account: &str, period: Option<&Period>) -> usize {
+        self.months
+            .iter()
+            .filter(|month| period.is_none_or(|period| period.contains(month)))
+            .filter_map(|month| {
+                self.account_missing_rates
+                    .get(&(account.to_string(), *month))
+            })
+            .sum()
+    }
+
+    /**
+     * Returns a row for each account with transactions in the period at the index, with how much
+     * it changed in the period. Without a period, this is the account's balance.
+     */
+    pub fn get_account_rows(
+        &self,
+        index: Option<usize>,
+        currencies: &[ReportCurrency],
+    ) -> Vec<Vec<String>> {
+        let period = self.get_period_at_index(index);
+        self.accounts
+            .iter()
+            .filter_map(|account| {
+                let sums = self.get_account_sums(account, period, currencies);
+                let missing_rates = self.get_account_missing_rates(account, period);
+                sums.iter().any(Option::is_some).then(|| {
+                    std::iter::once(format!("\n= account {}", account))
+                        .chain(sums.into_iter().zip(currencies).map(|(sum, currency)| {
+                            let text = sum.unwrap_or_default().format_in(currency.code());
+                            format!(
+                                "\n{}\n",
+                                Self::flag_missing_rates(text, currency, missing_rates)
+                            )
+                        }))
+                        .collect()
+                })
+            })
+            .collect()
+    }
+
+    /** Returns the labels of the periods, from the oldest */
+    pub fn get_period_labels(&self) -> Vec<String> {
+        self.periods
+            .iter()
+            .rev()
+            .map(|period| period.to_string())
+            .collect()
+    }
+
+    /** Returns the sum of a category in every period, from the oldest */
+    pub fn get_period_values(
+        &self,
+        direction_and_category: &DirectionAndCategory,
+        currency: &ReportCurrency,
+    ) -> Vec<f64> {
+        self.periods
+            .iter()
+            .rev()
+            .map(|period| {
+                self.get_period_sum(direction_and_category, period, currency)
+                    .unwrap_or_default()
+                    .to_f64()
+            })
+            .collect()
+    }
+
+    /** Returns the categories of the direction, without its total */
+    pub fn get_direction_categories(&self, direction: Direction) -> Vec<DirectionAndCategory> {
+        self.categories
+            .iter()
+            .filter(|(category_direction, category)| {
+                *category_direction == Some(direction) && category.is_some()
+            })
+            .cloned()
+            .collect()
+    }
+
+    pub fn get_period_rows(
+        &self,
+        direction_and_category: &DirectionAndCategory,
+        currencies: &[ReportCurrency],
+    ) -> Vec<(String, Vec<String>)> {
+        self.periods
+            .iter()
+            .map(|period| {
+                let period_str = format!("\n{}", period);
+                let category_amounts =
+                    self.format_sums(direction_and_category, Some(period), currencies);
+                (period_str, category_amounts)
+            })
+            .collect()
+    }
+
+    /**
+     * The number of months of the period from the report's first month to its last, so a year
+     * that is still going, or that the file starts in the middle of, isn't counted as 12 months
+     */
+    fn covered_months_len(&self, period: &Period) -> i64 {
+        let (Some(first), Some(last)) = (self.months.last(), self.months.first()) else {
+            return 0;
+        };
+        let (first_month, last_month) = period.month_range();
+        (first_month..=last_month)
+            .map(|month| (period.year(), month))
+            .filter(|month| first <= month && month <= last)
+            .count() as i64
+    }
+
+    /**
+     * Returns how much of the category's budget was used in the period at the index, in the home
+     * currency. Monthly budgets are multiplied by the months of the period the report covers, and
+     * yearly budgets are compared to the sum of the year up to the end of the period.
+     */
+    pub fn get_budget_progress(
+        &self,
+        direction_and_category: &DirectionAndCategory,
+        index: Option<usize>,
+        budgets: &Budgets,
+    ) -> Option<BudgetProgress> {
+        let budget = budgets.get(direction_and_category)?;
+        let period = self.get_period_at_index(index)?;
+        let home = self
+            .currencies
+            .iter()
+            .find(|currency| matches!(currency, ReportCurrency::Home(_)))?;
+        let (budget, actual) = match budget.period {
+            BudgetPeriod::Monthly => (
+                budget.amount * self.covered_months_len(period),
+                self.get_period_sum(direction_and_category, period, home)
+                    .unwrap_or_default(),
+            ),
+            BudgetPeriod::Yearly => (
+                budget.amount,
+                self.months
+                    .iter()
+                    .filter(|month| period.year_to_date_contains(month))
+                    .filter_map(|month| self.get_sum(direction_and_category, month, home))
+                    .sum(),
+            ),
+        };
+        Some(BudgetProgress { budget, actual })
+    }
+
+    /**
+     * Writes the sums in the currency as a pivot table - a row per direction and category, and a
+     * column per period from the oldest, followed by the total and its share of the direction.
+     * The last rows are the net cash flow and the savings rate.
+     * Returns how many transactions are left out of the sums for missing an exchange rate.
+     */
+    pub fn write_csv(
+        &self,
+        file_path: &Path,
+        currency: &ReportCurrency,
+        cash_flow: &CashFlow,
+    ) -> Result<usize> {
+        let mut writer = WriterBuilder::new().from_path(file_path)?;
+        let periods: Vec<&Period> = self.periods.iter().rev().collect();
+        let header = ["Direction".to_string(), "Category".to_string()]
+            .into_iter()
+            .chain(periods.iter().map(|period| period.to_string()))
+            .chain(["Total".to_string(), "Share".to_string()]);
+        writer.write_record(header)?;
+        for direction_and_category in &self.categories {
+            let sums: Vec<Money> = periods
+                .iter()
+                .map(|period| {
+                    self.get_period_sum(direction_and_category, period, currency)
+                        .unwrap_or_default()
+                })
+                .collect();
+            let total: Money = sums.iter().sum();
+            // like in the report page, sums that are missing exchange rates are flagged with a `!`
+            let format_sum = |sum: &Money, period: Option<&Period>| {
+                Self::flag_missing_rates(
+                    sum.format_in(currency.code()),
+                    currency,
+                    self.get_missing_rates(direction_and_category, period),
+                )
+            };
+            let record = [
+                direction_and_category
+                    .0
+                    .map(|direction| direction.to_string())
+                    .unwrap_or_default(),
+                direction_and_category.1.clone().unwrap_or("*".to_string()),
+            ]
+            .into_iter()
+            .chain(
+                sums.iter()
+                    .zip(&periods)
+                    .map(|(sum, period)| format_sum(sum, Some(period))),
+            )
+            .chain(std::iter::once(format_sum(&total, None)))
+            .chain(std::iter::once(Self::format_share(self.get_share(
+                direction_and_category,
+                None,
+                currency,
+            ))));
+            writer.write_record(record)?;
+        }
+        // the share column is left empty
+        let (nets, savings_rates): (Vec<String>, Vec<String>) = periods
+            .iter()
+            .map(|period| Some(*period))
+            .chain(std::iter::once(None))
+            .map(|period| self.format_cash_flow(period, currency, cash_flow))
+            .chain(std::iter::once(("".to_string(), "".to_string())))
+            .unzip();
+        writer.write_record(
+            ["= net".to_string(), cash_flow.label()]
+                .into_iter()
+                .chain(nets),
+        )?;
+        writer.write_record(
+            ["= savings rate".to_string(), "".to_string()]
+                .into_iter()
+                .chain(savings_rates),
+        )?;
+        writer.flush()?;
+        Ok(match currency {
+            ReportCurrency::Home(_) => self.missing_rates_count(),
+            ReportCurrency::Original(_) => 0,
+        })
+    }
+
+    pub fn get_period_at_index(&self, index: Option<usize>) -> Option<&Period> {
+        index.and_then(|index| self.periods.get(index))
+    }
+
+    /** Returns a vector of categories that have a non-0 value in any of the currencies for the given period */
+    pub fn get_categories_for_period_by_index(
+        &self,
+        index: Option<usize>,
+        currencies: &[ReportCurrency],
+    ) -> Vec<DirectionAndCategory> {
+        let period = self.get_period_at_index(index);
+        self.categories
+            .iter()
+            .filter(|direction_and_category| {
+                self.get_sums(direction_and_category, period, currencies)
+                    .into_iter()
+                    .flatten()
+                    .any(|amount| period.is_none() || !amount.is_zero())
+            })
+            .cloned()
+            .collect()
+    }
+
+    /** Returns the category label for a period index and a category index */
+    pub fn get_category_by_index_for_period_at_index(
+        &self,
+        period_index: Option<usize>,
+        category_index: Option<usize>,
+        currencies: &[ReportCurrency],
+    ) -> DirectionAndCategory {
+        let categories = self.get_categories_for_period_by_index(period_index, currencies);
+        category_index.map_or((None, None), |category_index| {
+            categories
+                .get(category_index)
+                .map_or((None, None), |category| category.clone())
+        })
+    }
+
+    /**
+     * Returns the category label, its sum in every given currency, and in the main currency its
+     * share of the direction and its change from the previous period and from a year before, for
+     * each category shown in the period.
+     */
+    pub fn get_category_rows_for_period_by_index(
+        &self,
+        index: Option<usize>,
+        currencies: &[ReportCurrency],
+        main_currency: Option<&ReportCurrency>,
+    ) -> Vec<Vec<String>> {
+        let period = self.get_period_at_index(index);
+        self.get_categories_for_period_by_index(index, currencies)
+            .iter()
+            .map(|direction_and_category| {
+                let label = format!(
+                    "\n{} - {}",
+                    direction_and_category
+                        .0
+                        .map_or("*".to_string(), |direction| direction.to_string()),
+                    direction_and_category
+                        .1
+                        .as_ref()
+                        .unwrap_or(&"*".to_string())
+                );
+                let sums = self
+                    .format_sums(direction_and_category, period, currencies)
+                    .into_iter()
+                    .map(|sum| format!("\n{}\n", sum));
+                let share =
+                    Self::format_share(main_currency.and_then(|currency| {
+                        self.get_share(direction_and_category, period, currency)
+                    }));
+                let change_from = |earlier: Option<Period>| match (period, earlier, main_currency) {
+                    (Some(period), Some(earlier), Some(currency)) => Self::format_change(
+                        self.get_change(direction_and_category, period, &earlier, currency),
+                        currency,
+                    ),
+                    _ => "".to_string(),
+                };
+                let previous_change = change_from(period.map(Period::previous));
+                let last_year_change = change_from(period.and_then(Period::last_year));
+                std::iter::once(label)
+                    .chain(sums)
+                    .chain([format!("\n{}\n", share), previous_change, last_year_change])
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+#[derive(Default, Clone)]
 pub struct Filter {
     year: Option<i32>,
     month: Option<u8>,
+    /** Typed in the month column as `Q1` to `Q4` */
+    quarter: Option<u8>,
     details: String,
     category: String,
     method: String,
     direction: String,
     currency: String,
+    destination: String,
+    splits: String,
+    tags: String,
+    notes: String,
 }
 
 impl Filter {
     pub fn new(
-        direction: Option<String>,
+        direction: Option<Direction>,
         category: Option<String>,
-        month_in_year: Option<MonthInYear>,
+        tag: Option<String>,
+        period: Option<Period>,
+        currency: Option<String>,
     ) -> Self {
-        let (year, month) = month_in_year
-            .map(|(y, m)| (Some(y), Some(m)))
-            .unwrap_or((None, None));
+        let (year, month, quarter) = match period {
+            Some(Period::Month((year, month))) => (Some(year), Some(month), None),
+            Some(Period::Quarter(year, quarter)) => (Some(year), None, Some(quarter)),
+            Some(Period::Year(year)) => (Some(year), None, None),
+            None => (None, None, None),
+        };
         Self {
             year,
             month,
+            quarter,
             details: "".to_string(),
             category: category.unwrap_or_default(),
             method: "".to_string(),
-            direction: direction.unwrap_or_default(),
-            currency: "".to_string(),
+            direction: direction
+                .map(|direction| direction.to_string())
+                .unwrap_or_default(),
+            currency: currency.unwrap_or_default(),
+            destination: "".to_string(),
+            splits: "".to_string(),
+            tags: tag.unwrap_or_default(),
+            notes: "".to_string(),
         }
     }
 
     pub fn generate_row(&self) -> Row<'_> {
-        let cells: Vec<Cell> = (0..6)
-            .into_iter()
+        let cells: Vec<Cell> = (0..Self::column_names().len())
             .map(|field| self.get_column_text(field))
             .map(|text| Cell::from(Text::from(format!("\n{}\n", text))))
             .collect();
@@ -479,15 +1718,20 @@ impl Filter {
                 Some(year) => format!("{:04}", year),
                 None => "".to_string(),
             },
-            1 => match self.month {
-                Some(month) => format!("{:02}", month),
-                None => "".to_string(),
+            1 => match (self.month, self.quarter) {
+                (Some(month), _) => format!("{:02}", month),
+                (None, Some(quarter)) => format!("Q{}", quarter),
+                (None, None) => "".to_string(),
             },
             2 => self.details.clone(),
             3 => self.category.clone(),
             4 => self.method.clone(),
             5 => self.direction.clone(),
             6 => self.currency.clone(),
+            7 => self.destination.clone(),
+            8 => self.splits.clone(),
+            9 => self.tags.clone(),
+            10 => self.notes.clone(),
             _ => "".to_string(),
         };
         output
@@ -505,11 +1749,18 @@ impl Filter {
                 return Ok(());
             }
             1 => {
-                if input.is_empty() {
-                    self.month = None;
+                // an invalid input keeps the month or quarter that was there
+                (self.month, self.quarter) = if let Some(quarter) = input.strip_prefix(['Q', 'q']) {
+                    let quarter = quarter.parse::<u8>().map_err(|e| e.to_string())?;
+                    if !(1..=4).contains(&quarter) {
+                        return Err(format!("Invalid quarter: Q{}", quarter));
+                    }
+                    (None, Some(quarter))
+                } else if input.is_empty() {
+                    (None, None)
                 } else {
-                    self.month = Some(input.parse::<u8>().map_err(|e| e.to_string())?);
-                }
+                    (Some(input.parse::<u8>().map_err(|e| e.to_string())?), None)
+                };
                 return Ok(());
             }
             2 => self.details = input.to_string(),
@@ -517,15 +1768,15 @@ impl Filter {
             4 => self.method = input.to_string(),
             5 => self.direction = input.to_string(),
             6 => self.currency = input.to_string(),
+            7 => self.destination = input.to_string(),
+            8 => self.splits = input.to_string(),
+            9 => self.tags = input.to_string(),
+            10 => self.notes = input.to_string(),
             _ => return Err("Invalid field index".to_string()),
         }
         Ok(())
     }
 
-    pub fn column_widths() -> Vec<u16> {
-        vec![11, 10, 100, 15, 9, 9, 9]
-    }
-
     pub fn column_names() -> Vec<String> {
         vec![
             "Year",
@@ -535,6 +1786,10 @@ impl Filter {
             "Method",
             "Direction",
             "Currency",
+            "To",
+            "Splits",
+            "Tags",
+            "Notes",
         ]
         .into_iter()
         .map(|s| s.to_string())
@@ -547,72 +1802,235 @@ pub struct TransactionsTable {
     file_path: PathBuf,
     file_type: FileType,
     filter: Filter,
+    pub date_format: DateFormat,
+    history: History,
+    duplicate_window_days: i64,
+    /** The currency of new rows when the filter has none */
+    home_currency: String,
+    rules: Rules,
+    /** The csv columns the schema doesn't know, kept so saving doesn't drop them */
+    extra_columns: Vec<String>,
+    /** The recurring transactions whose occurrences were added in this session */
+    recurring: RecurringTransactions,
 }
 
 impl TransactionsTable {
-    pub fn new(file_path: PathBuf) -> Self {
+    pub fn new(
+        file_path: PathBuf,
+        date_format: DateFormat,
+        duplicate_window_days: i64,
+        home_currency: String,
+    ) -> Self {
         let file_type = FileType::new(&file_path);
         Self {
             transactions: Vec::new(),
             file_path,
             file_type,
             filter: Filter::default(),
+            date_format,
+            history: History::default(),
+            duplicate_window_days,
+            home_currency,
+            rules: Rules::default(),
+            extra_columns: vec![],
+            recurring: RecurringTransactions::default(),
         }
     }
 
-    pub fn load(&mut self) -> Result<()> {
-        match self.file_type {
+    /** Loads the transactions, returning the directions that were changed from the file */
+    pub fn load(&mut self) -> Result<DirectionMigration> {
+        let migration = match self.file_type {
             FileType::Json => self.load_from_json(),
             FileType::Csv => self.load_from_csv(),
             FileType::Unknown => bail!("File type unknown"),
+        }?;
+        if migration.normalized > 0 || !migration.unknown.is_empty() {
+            tracing::info!(
+                "normalized the direction of {} transactions in {:?}, unknown directions: {:?}",
+                migration.normalized,
+                self.file_path,
+                migration.unknown
+            );
         }
+        Ok(migration)
     }
 
-    pub fn load_from_json(&mut self) -> Result<()> {
+    /**
+     * Adds the due occurrences of the recurring transactions kept next to the transactions file,
+     * returning how many were added. They are only saved once they are kept or edited.
+     */
+    pub fn materialize_recurring(&mut self) -> Result<usize> {
+        let path = RecurringTransactions::path_for(&self.file_path);
+        self.recurring = RecurringTransactions::load(&path)?;
+        let due = self
+            .recurring
+            .materialize(&self.transactions, SimpleDate::today());
+        let count = due.len();
+        if count > 0 {
+            tracing::info!("added {} recurring transactions from {:?}", count, path);
+            self.transactions.extend(due);
+            self.transactions.sort();
+        }
+        Ok(count)
+    }
+
+    /** Loads the transactions, returning the directions that were changed */
+    pub fn load_from_json(&mut self) -> Result<DirectionMigration> {
         let file_string = fs::read_to_string(&self.file_path)?;
-        self.transactions = serde_json::from_str(&file_string)?;
+        let values: Vec<serde_json::Value> = serde_json::from_str(&file_string)?;
+        let mut migration = DirectionMigration::default();
+        self.transactions = Vec::new();
+        for (i, mut value) in values.into_iter().enumerate() {
+            let stored = value
+                .get("direction")
+                .and_then(|stored| stored.as_str())
+                .map(str::to_string);
+            if let Some(direction) = stored
+                .as_deref()
+                .and_then(|stored| migration.migrate(stored))
+            {
+                value["direction"] = direction.to_string().into();
+            }
+            let transaction: Transaction = serde_json::from_value(value)
+                .map_err(eyre::Report::from)
+                .and_then(|transaction: Transaction| {
+                    transaction.check_currency_digits().map_err(|e| eyre!(e))?;
+                    Ok(transaction)
+                })
+                .wrap_err_with(|| format!("transaction {}", i + 1))
+                .wrap_err_with(|| format!("Failed to load {:?}", self.file_path))?;
+            if let Some(stored) = &stored {
+                migration.note_unknown(format!("transaction {}", i + 1), stored, &transaction);
+            }
+            self.transactions.push(transaction);
+        }
         self.transactions.sort();
-        Ok(())
+        Ok(migration)
     }
 
-    pub fn load_from_csv(&mut self) -> Result<()> {
+    /**
+     * Loads the transactions, returning the directions that were changed.
+     * Columns can be missing or in any order, and the unknown columns are kept for saving.
+     */
+    pub fn load_from_csv(&mut self) -> Result<DirectionMigration> {
+        let file_string = schema::read_text(fs::read(&self.file_path)?)
+            .wrap_err_with(|| format!("Failed to load {:?}", self.file_path))?;
+        let (version, csv) = schema::read_version(&file_string)
+            .wrap_err_with(|| format!("Failed to load {:?}", self.file_path))?;
+        // the schema line is not part of the csv, but counts in the line numbers
+        let skipped_lines = u64::from(csv.len() < file_string.len());
         let mut reader = ReaderBuilder::new()
             .has_headers(true)
-            .from_path(&self.file_path)?;
+            .from_reader(csv.as_bytes());
+        let headers = reader.headers()?.clone();
+        let layout = CsvLayout::new(&headers)
+            .wrap_err_with(|| format!("Failed to load {:?}", self.file_path))?;
+        // versions 1 and 2 are read the same way: the columns are found by their headers,
+        // and the columns a version 1 file doesn't have are left empty
+        tracing::info!(
+            "loading {:?} with schema version {}, extra columns {:?}",
+            self.file_path,
+            version,
+            layout.extra_columns()
+        );
+        let direction_index = headers.iter().position(|header| header == "direction");
+        let mut migration = DirectionMigration::default();
         self.transactions = Vec::new();
-        for result in reader.deserialize() {
-            let transaction = result?;
+        for result in reader.records() {
+            let mut record = result
+                .map_err(|error| schema::describe_record_error(skipped_lines, error))
+                .wrap_err_with(|| format!("Failed to load {:?}", self.file_path))?;
+            let line = record.position().map_or(0, |position| position.line()) + skipped_lines;
+            let stored = direction_index.map(|i| record[i].to_string());
+            if let Some(direction_index) = direction_index {
+                if let Some(direction) = migration.migrate(&record[direction_index]) {
+                    record = record
+                        .iter()
+                        .enumerate()
+                        .map(|(i, field)| match i == direction_index {
+                            true => direction.to_string(),
+                            false => field.to_string(),
+                        })
+                        .collect();
+                }
+            }
+            let (known, extra) = layout.split(&record);
+            let mut transaction: Transaction = known
+                .deserialize(Some(layout.known_headers()))
+                .map_err(|error| layout.describe_error(line, &known, error))
+                .and_then(|transaction: Transaction| {
+                    transaction
+                        .check_currency_digits()
+                        .map_err(|e| eyre!("line {}: {}", line, e))?;
+                    Ok(transaction)
+                })
+                .wrap_err_with(|| format!("Failed to load {:?}", self.file_path))?;
+            transaction.extra = extra;
+            if let Some(stored) = &stored {
+                migration.note_unknown(format!("line {}", line), stored, &transaction);
+            }
             self.transactions.push(transaction);
         }
+        self.extra_columns = layout.extra_columns().to_vec();
         self.transactions.sort();
-        Ok(())
+        Ok(migration)
     }
 
+    /**
+     * Saves the transactions, leaving out the recurring occurrences that are still waiting for
+     * review, and then records in the recurring file which occurrences were reviewed
+     */
     pub fn save_transactions(&mut self) -> Result<()> {
         match self.file_type {
             FileType::Json => self.save_to_json(),
             FileType::Csv => self.save_to_csv(),
             FileType::Unknown => bail!("File type unknown"),
+        }?;
+        if !self.recurring.is_empty() {
+            self.recurring.mark_reviewed(&self.transactions);
+            self.recurring
+                .save(&RecurringTransactions::path_for(&self.file_path))?;
         }
+        Ok(())
+    }
+
+    /** The transactions that are written to the file */
+    fn saved_transactions(&self) -> impl Iterator<Item = &Transaction> {
+        self.transactions
+            .iter()
+            .filter(|transaction| !transaction.needs_review)
     }
 
     pub fn save_to_json(&mut self) -> Result<()> {
         self.transactions.sort();
         fs::write(
             &self.file_path,
-            serde_json::to_string_pretty(&self.transactions)?,
+            serde_json::to_string_pretty(&self.saved_transactions().collect::<Vec<_>>())?,
         )?;
         Ok(())
     }
 
+    /** Saves the transactions with the current schema, followed by the extra columns */
     pub fn save_to_csv(&mut self) -> Result<()> {
         self.transactions.sort();
-        // let file = File::create("transactions.csv")?;
+        let mut file = File::create(&self.file_path)?;
+        writeln!(file, "{}", schema::version_line())?;
         let mut writer = WriterBuilder::new()
             .delimiter(b',')
-            .from_path(&self.file_path)?;
-        for transaction in &self.transactions {
-            writer.serialize(transaction)?;
+            .has_headers(false)
+            .from_writer(file);
+        writer.write_record(
+            COLUMNS
+                .iter()
+                .copied()
+                .chain(self.extra_columns.iter().map(String::as_str)),
+        )?;
+        for transaction in self.saved_transactions() {
+            // transactions added in this session have no values for the extra columns
+            let extra = (0..self.extra_columns.len())
+                .map(|i| transaction.extra.get(i).map_or("", String::as_str))
+                .collect::<Vec<&str>>();
+            writer.serialize((transaction, extra))?;
         }
         writer.flush()?;
         Ok(())
@@ -622,55 +2040,174 @@ impl TransactionsTable {
         self.filter = filter;
     }
 
+    fn matches_filter(&self, transaction: &Transaction) -> bool {
+        let dir_matches =
+            matches_ignore_case(&transaction.direction.to_string(), &self.filter.direction);
+        // a split transaction is also found by the categories of its splits
+        let ctg_matches = std::iter::once(transaction.category.as_str())
+            .chain(
+                transaction
+                    .splits
+                    .iter()
+                    .map(|split| split.category.as_str()),
+            )
+            .any(|category| matches_ignore_case(category, &self.filter.category));
+        let details_matches = matches_ignore_case(&transaction.details, &self.filter.details);
+        let method_matches = matches_ignore_case(&transaction.method, &self.filter.method);
+        let currency_matches = matches_ignore_case(&transaction.currency, &self.filter.currency);
+        let destination_matches =
+            matches_ignore_case(&transaction.destination, &self.filter.destination);
+        let splits_matches =
+            matches_ignore_case(&transaction.splits.to_string(), &self.filter.splits);
+        // tags match whole, so `car` doesn't find `carpool`
+        let tags_matches = transaction
+            .tags
+            .contains_all(&Tags::from_str(&self.filter.tags).unwrap_or_default());
+        let notes_matches = matches_ignore_case(&transaction.notes, &self.filter.notes);
+        let year_matches = self
+            .filter
+            .year
+            .is_none_or(|year| transaction.date.year == year);
+        let month_matches = self
+            .filter
+            .month
+            .is_none_or(|month| transaction.date.month == month);
+        let quarter_matches = self
+            .filter
+            .quarter
+            .is_none_or(|quarter| quarter_of(transaction.date.month) == quarter);
+
+        dir_matches
+            && ctg_matches
+            && details_matches
+            && method_matches
+            && currency_matches
+            && destination_matches
+            && splits_matches
+            && tags_matches
+            && notes_matches
+            && year_matches
+            && month_matches
+            && quarter_matches
+    }
+
     pub fn filtered_transactions(&self) -> impl Iterator<Item = &Transaction> {
-        self.transactions.iter().filter(|transaction| {
-            let dir_matches = matches_ignore_case(&transaction.direction, &self.filter.direction);
-            let ctg_matches = matches_ignore_case(&transaction.category, &self.filter.category);
-            let details_matches = matches_ignore_case(&transaction.details, &self.filter.details);
-            let method_matches = matches_ignore_case(&transaction.method, &self.filter.method);
-            let year_matches = self
-                .filter
-                .year
-                .map_or(true, |year| transaction.date.year == year);
-            let month_matches = self
-                .filter
-                .month
-                .map_or(true, |month| transaction.date.month == month);
-
-            return dir_matches
-                && ctg_matches
-                && details_matches
-                && method_matches
-                && year_matches
-                && month_matches;
-        })
+        self.transactions
+            .iter()
+            .filter(|transaction| self.matches_filter(transaction))
+    }
+
+    /** Returns the indices of the filtered transactions in the unfiltered table */
+    fn filtered_indices(&self) -> impl Iterator<Item = usize> + '_ {
+        self.transactions
+            .iter()
+            .enumerate()
+            .filter(|(_, transaction)| self.matches_filter(transaction))
+            .map(|(index, _)| index)
+    }
+
+    /** Returns the row of a transaction in the filtered table, if the filter shows it */
+    pub fn get_filtered_row(&self, index: usize) -> Option<usize> {
+        self.filtered_indices().position(|i| i == index)
     }
 
     pub fn new_transaction_from_filter(&mut self) -> Transaction {
         let last_transaction_date = self.filtered_transactions().last().unwrap().date;
         Transaction {
             date: last_transaction_date,
-            direction: self.filter.direction.clone(),
+            direction: self.filter.direction.parse().unwrap_or_default(),
             category: self.filter.category.clone(),
             details: self.filter.details.clone(),
             method: self.filter.method.clone(),
-            currency: "".to_string(),
-            amount: 0.0,
+            currency: match self.filter.currency.is_empty() {
+                true => self.home_currency.clone(),
+                false => self.filter.currency.clone(),
+            },
+            destination: self.filter.destination.clone(),
+            splits: Splits::default(),
+            tags: Tags::from_str(&self.filter.tags).unwrap_or_default(),
+            notes: "".to_string(),
+            amount: Money::default(),
+            is_new: true,
+            needs_review: false,
+            extra: vec![],
         }
     }
 
     pub fn new_transaction(&mut self) {
         let new_transaction = self.new_transaction_from_filter();
+        self.history.record(Edit::Insert {
+            index: self.transactions.len(),
+            transaction: new_transaction.clone(),
+        });
         self.transactions.push(new_transaction);
     }
 
+    /** Appends transactions to the end of the table, each one can be undone separately */
+    pub fn append_transactions(&mut self, transactions: Vec<Transaction>) {
+        for transaction in transactions {
+            let mut transaction = self.rules.apply(&transaction);
+            transaction.is_new = true;
+            self.history.record(Edit::Insert {
+                index: self.transactions.len(),
+                transaction: transaction.clone(),
+            });
+            self.transactions.push(transaction);
+        }
+        self.history.seal();
+    }
+
     pub fn delete_transaction(&mut self, row: usize) {
-        let row = self.get_unfiltered_row(row);
-        if row < self.len() {
-            self.transactions.remove(row);
+        let index = self.get_unfiltered_row(row);
+        self.remove_transaction(index);
+    }
+
+    fn remove_transaction(&mut self, index: usize) {
+        if index < self.len() {
+            let transaction = self.transactions.remove(index);
+            self.history.record(Edit::Delete { index, transaction });
         }
     }
 
+    fn apply_edit(&mut self, edit: &Edit) {
+        match edit {
+            Edit::Update { index, after, .. } => {
+                if let Some(transaction) = self.transactions.get_mut(*index) {
+                    *transaction = (**after).clone();
+                }
+            }
+            Edit::Insert { index, transaction } => {
+                let index = (*index).min(self.transactions.len());
+                self.transactions.insert(index, transaction.clone());
+            }
+            Edit::Delete { index, .. } => {
+                if *index < self.transactions.len() {
+                    self.transactions.remove(*index);
+                }
+            }
+            Edit::Batch(edits) => edits.iter().for_each(|edit| self.apply_edit(edit)),
+        }
+    }
+
+    /** Reverts the last edit, returning the index of the transaction it changed */
+    pub fn undo(&mut self) -> Option<usize> {
+        let edit = self.history.undo()?;
+        self.apply_edit(&edit);
+        Some(edit.index())
+    }
+
+    /** Applies the last undone edit again, returning the index of the transaction it changed */
+    pub fn redo(&mut self) -> Option<usize> {
+        let edit = self.history.redo()?;
+        self.apply_edit(&edit);
+        Some(edit.index())
+    }
+
+    /** Stops merging the following updates into the last edit */
+    pub fn seal_history(&mut self) {
+        self.history.seal();
+    }
+
     pub fn update_transaction(
         &mut self,
         row: usize,
@@ -679,7 +2216,20 @@ impl TransactionsTable {
     ) -> Result<(), String> {
         let row = self.get_unfiltered_row(row);
         if let Some(transaction) = self.transactions.get_mut(row) {
-            transaction.mutate_field(column, input)?
+            let before = transaction.clone();
+            transaction.mutate_field(column, input, &self.date_format)?;
+            if before.get_column_text(column, &self.date_format)
+                != transaction.get_column_text(column, &self.date_format)
+            {
+                transaction.needs_review = false;
+                let after = transaction.clone();
+                self.history.record(Edit::Update {
+                    index: row,
+                    column,
+                    before: Box::new(before),
+                    after: Box::new(after),
+                });
+            }
         }
         Ok(())
     }
@@ -688,84 +2238,483 @@ impl TransactionsTable {
         let row = self.get_unfiltered_row(row);
         self.transactions
             .get(row)
-            .map(|transaction| transaction.get_column_text(column))
-            .flatten()
+            .and_then(|transaction| transaction.get_column_text(column, &self.date_format))
     }
 
     fn get_unfiltered_row(&self, row: usize) -> usize {
-        if let Some(transaction) = self.filtered_transactions().take(row + 1).last() {
-            if let Some((row, _)) = self
-                .transactions
-                .iter()
-                .enumerate()
-                .find(|(_, t)| *t == transaction)
-            {
-                return row;
-            }
-        }
-        0
+        self.filtered_indices()
+            .take(row + 1)
+            .last()
+            .unwrap_or(self.transactions.len())
     }
 
-    fn find_recommended_transactions_by_field(
+    /**
+     * Returns ranked suggestions for the cell.
+     * With some input, the suggestions are values of the same field that match it.
+     * Without input, they are the values of the field in transactions with the same details.
+     * The column's policy decides which of these are offered.
+     */
+    pub fn suggestions(
         &self,
         row: usize,
-        field: &TransactionField,
+        column: usize,
         input: &str,
-    ) -> Option<&Transaction> {
-        let row = self.get_unfiltered_row(row);
-        self.transactions
+        policy: AutocompletePolicy,
+    ) -> Vec<String> {
+        let Some(field) = TransactionField::get(column) else {
+            return vec![];
+        };
+        let allowed = if input.is_empty() {
+            policy.prefills_from_details()
+        } else {
+            policy.completes_input()
+        };
+        if !allowed {
+            return vec![];
+        }
+        let index = self.get_unfiltered_row(row);
+        let other_transactions = self
+            .transactions
             .iter()
-            .take(row)
-            .rev()
-            .find(|transaction| transaction.get_field_text(field).starts_with(input))
+            .enumerate()
+            .filter(move |(i, _)| *i != index)
+            .map(|(_, transaction)| transaction);
+        if let (TransactionField::Tags, false) = (&field, input.is_empty()) {
+            return Self::tag_suggestions(other_transactions, input, policy);
+        }
+        if !input.is_empty() {
+            let values = other_transactions
+                .map(|transaction| transaction.get_field_text(&field, &self.date_format));
+            return rank_suggestions(values, input, policy);
+        }
+        let details = match (&field, self.transactions.get(index)) {
+            // notes belong to one transaction, so they are never copied from the same details
+            (TransactionField::Notes, _) => return vec![],
+            (_, Some(transaction)) if !transaction.details.is_empty() => {
+                transaction.details.clone()
+            }
+            _ => return vec![],
+        };
+        let values = other_transactions
+            .filter(|transaction| transaction.details.eq_ignore_ascii_case(&details))
+            .map(|transaction| transaction.get_field_text(&field, &self.date_format));
+        rank_suggestions(values, input, policy)
     }
 
-    pub fn update_recommended_input(
-        &mut self,
-        row: usize,
-        column: usize,
+    /** Completes the last tag being typed, keeping the tags typed before it */
+    fn tag_suggestions<'a>(
+        other_transactions: impl Iterator<Item = &'a Transaction>,
         input: &str,
-    ) -> Option<String> {
-        if let Some(field) = TransactionField::get(column) {
-            let row = self.get_unfiltered_row(row);
-            if input.chars().count() > 0 {
-                // look for a previous input of the same field that starts with the given input
-                return self
-                    .find_recommended_transactions_by_field(row, &field, input)
-                    .map(|transaction| transaction.get_field_text(&field));
-            } else {
-                // look for a transaction with the same details and provide the relevant column
-                let input_details = self
-                    .transactions
-                    .get(row)
-                    .map_or("".to_string(), |transaction| {
-                        transaction.get_field_text(&TransactionField::Details)
-                    });
-                return self
-                    .find_recommended_transactions_by_field(
-                        row,
-                        &TransactionField::Details,
-                        &input_details,
-                    )
-                    .map(|transaction| transaction.get_field_text(&field));
-            }
-        }
-        None
+        policy: AutocompletePolicy,
+    ) -> Vec<String> {
+        let (typed, last) = match input.rsplit_once(',') {
+            Some((typed, last)) => (format!("{}, ", typed.trim_end()), last.trim_start()),
+            None => ("".to_string(), input),
+        };
+        let typed_tags = Tags::from_str(&typed).unwrap_or_default();
+        let values = other_transactions
+            .flat_map(|transaction| transaction.tags.iter())
+            .filter(|tag| {
+                !typed_tags
+                    .iter()
+                    .any(|typed| typed.eq_ignore_ascii_case(tag))
+            })
+            .cloned();
+        rank_suggestions(values, last, policy)
+            .into_iter()
+            .map(|tag| format!("{}{}", typed, tag))
+            .collect()
     }
 
     pub fn len(&self) -> usize {
         self.transactions.len()
     }
 
+    /** Returns the original of the transaction in the filtered row, if it looks like a duplicate */
+    pub fn duplicate_of(&self, row: usize) -> Option<&Transaction> {
+        let index = self.get_unfiltered_row(row);
+        find_duplicate(&self.transactions, index, self.duplicate_window_days)
+            .and_then(|duplicate_index| self.transactions.get(duplicate_index))
+    }
+
+    /** Returns for each filtered row whether it looks like a duplicate */
+    pub fn filtered_duplicate_flags(&self) -> Vec<bool> {
+        self.filtered_indices()
+            .map(|index| {
+                find_duplicate(&self.transactions, index, self.duplicate_window_days).is_some()
+            })
+            .collect()
+    }
+
+    pub fn duplicates_count(&self) -> usize {
+        (0..self.transactions.len())
+            .filter(|index| {
+                find_duplicate(&self.transactions, *index, self.duplicate_window_days).is_some()
+            })
+            .count()
+    }
+
+    /** Merges the duplicate in the filtered row into its original and removes the duplicate */
+    pub fn merge_duplicate(&mut self, row: usize) {
+        let index = self.get_unfiltered_row(row);
+        if let Some(original_index) =
+            find_duplicate(&self.transactions, index, self.duplicate_window_days)
+        {
+            let duplicate = self.transactions[index].clone();
+            let before = self.transactions[original_index].clone();
+            self.transactions[original_index].merge_from(&duplicate);
+            let after = self.transactions[original_index].clone();
+            self.transactions.remove(index);
+            // merging is undone in one step, bringing back the duplicate with the original
+            self.history.record(Edit::Batch(vec![
+                Edit::Update {
+                    index: original_index,
+                    column: WHOLE_TRANSACTION,
+                    before: Box::new(before),
+                    after: Box::new(after),
+                },
+                Edit::Delete {
+                    index,
+                    transaction: duplicate,
+                },
+            ]));
+        }
+    }
+
+    pub fn set_rules(&mut self, rules: Rules) {
+        self.rules = rules;
+    }
+
+    /** Applies the matching rule to the transaction in the filtered row */
+    pub fn apply_rules(&mut self, row: usize) {
+        let index = self.get_unfiltered_row(row);
+        if let Some(change) = self
+            .transactions
+            .get(index)
+            .and_then(|transaction| self.rules.change(index, transaction))
+        {
+            self.apply_rule_changes(vec![change]);
+        }
+    }
+
+    /** Returns the changes the rules would make to the filtered transactions */
+    pub fn rule_changes(&self) -> Vec<RuleChange> {
+        self.filtered_indices()
+            .filter_map(|index| self.rules.change(index, &self.transactions[index]))
+            .collect()
+    }
+
+    pub fn apply_rule_changes(&mut self, changes: Vec<RuleChange>) {
+        for change in changes {
+            if let Some(transaction) = self.transactions.get_mut(change.index) {
+                *transaction = change.after.clone();
+                self.history.record(Edit::Update {
+                    index: change.index,
+                    column: WHOLE_TRANSACTION,
+                    before: Box::new(change.before),
+                    after: Box::new(change.after),
+                });
+                self.history.seal();
+            }
+        }
+    }
+
+    /**
+     * Keeps the transaction in the filtered row, so it is no longer checked for duplicates
+     * and no longer needs a review
+     */
+    pub fn keep_transaction(&mut self, row: usize) {
+        let index = self.get_unfiltered_row(row);
+        if let Some(transaction) = self.transactions.get_mut(index) {
+            transaction.is_new = false;
+            transaction.needs_review = false;
+        }
+    }
+
+    pub fn needs_review(&self, row: usize) -> bool {
+        self.transactions
+            .get(self.get_unfiltered_row(row))
+            .is_some_and(|transaction| transaction.needs_review)
+    }
+
     pub fn filtered_len(&self) -> usize {
         self.filtered_transactions().count()
     }
 
-    pub fn generate_report(&self) -> TransactionsReport {
-        TransactionsReport::new(&self.transactions)
+    pub fn summarize_accounts(&self, accounts: &Accounts) -> AccountsSummary {
+        accounts.summarize(&self.transactions)
+    }
+
+    pub fn generate_report(
+        &self,
+        home_currency: &str,
+        exchange_rates: &ExchangeRates,
+        grouping: Grouping,
+    ) -> TransactionsReport {
+        TransactionsReport::new(&self.transactions, home_currency, exchange_rates, grouping)
     }
 }
 
 fn matches_ignore_case(a: &str, b: &str) -> bool {
     a.to_lowercase().contains(&b.to_lowercase())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{budgets::Budget, exchange_rates::ExchangeRate};
+
+    fn date(day: u8) -> SimpleDate {
+        SimpleDate {
+            year: 2024,
+            month: 3,
+            day,
+        }
+    }
+
+    fn transaction(amount: &str, direction: Direction, destination: &str) -> Transaction {
+        let mut transaction = Transaction::new(
+            date(1),
+            Money::parse(amount).unwrap(),
+            "refund".to_string(),
+            "shopping".to_string(),
+            "card".to_string(),
+            direction,
+            "USD".to_string(),
+        );
+        transaction.destination = destination.to_string();
+        transaction
+    }
+
+    #[test]
+    fn negative_amounts_change_the_home_currency_accounts_with_their_sign() {
+        let rates = ExchangeRates::new(vec![ExchangeRate {
+            date: date(1),
+            from: "USD".to_string(),
+            to: "ILS".to_string(),
+            rate: 3.5,
+        }]);
+        let transactions = [
+            transaction("-50", Direction::Out, ""),
+            transaction("-20", Direction::Transfer, "savings"),
+        ];
+        let report = TransactionsReport::new(&transactions, "ILS", &rates, Grouping::Category);
+        let currencies = [
+            ReportCurrency::Original("USD".to_string()),
+            ReportCurrency::Home("ILS".to_string()),
+        ];
+        let sums = |account| report.get_account_sums(account, None, &currencies);
+        assert_eq!(
+            sums("card"),
+            vec![
+                Some(Money::parse("70").unwrap()),
+                Some(Money::parse("245").unwrap())
+            ]
+        );
+        assert_eq!(
+            sums("savings"),
+            vec![
+                Some(Money::parse("-20").unwrap()),
+                Some(Money::parse("-70").unwrap())
+            ]
+        );
+    }
+
+    #[test]
+    fn the_header_is_the_serialized_field_order() {
+        let mut writer = WriterBuilder::new().from_writer(vec![]);
+        writer
+            .serialize(transaction("1", Direction::Out, ""))
+            .unwrap();
+        let csv = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(csv.lines().next(), Some(COLUMNS.join(",").as_str()));
+    }
+
+    #[test]
+    fn saving_keeps_extra_columns_and_writes_the_schema_order() {
+        let file_path =
+            std::env::temp_dir().join(format!("hectec-{}-columns.csv", std::process::id()));
+        fs::write(
+            &file_path,
+            "receipt,direction,amount,date\n\
+             r-1,out,12.50,2024.03.01\n\
+             r-2,in,100,2024.03.02\n",
+        )
+        .unwrap();
+        let mut table = TransactionsTable::new(
+            file_path.clone(),
+            DateFormat::default(),
+            3,
+            "ILS".to_string(),
+        );
+        table.load().unwrap();
+        table.save_to_csv().unwrap();
+        let saved = fs::read_to_string(&file_path).unwrap();
+        fs::remove_file(&file_path).unwrap();
+        let lines: Vec<&str> = saved.lines().collect();
+        assert_eq!(lines[0], schema::version_line());
+        assert_eq!(lines[1], format!("{},receipt", COLUMNS.join(",")));
+        assert_eq!(lines[2], "2024.03.01,12.50,,,,out,,,,,,r-1");
+        assert_eq!(lines[3], "2024.03.02,100.00,,,,in,,,,,,r-2");
+    }
+
+    #[test]
+    fn merging_a_duplicate_is_undone_in_one_step() {
+        let mut table =
+            TransactionsTable::new(PathBuf::new(), DateFormat::default(), 3, "ILS".to_string());
+        let original = transaction("50", Direction::Out, "");
+        let mut duplicate = original.clone();
+        duplicate.is_new = true;
+        duplicate.notes = "from the bank".to_string();
+        duplicate.splits = "shopping=30; fees=20".parse().unwrap();
+        duplicate.tags = "trip".parse().unwrap();
+        table.transactions = vec![original.clone(), duplicate.clone()];
+        table.merge_duplicate(1);
+        assert_eq!(table.transactions.len(), 1);
+        assert_eq!(table.transactions[0].notes, "from the bank");
+        assert_eq!(table.transactions[0].splits, duplicate.splits);
+        assert_eq!(table.transactions[0].tags, duplicate.tags);
+
+        table.undo();
+        assert_eq!(table.transactions.len(), 2);
+        assert_eq!(table.transactions[0].notes, original.notes);
+        assert!(table.history.undo().is_none());
+
+        table.redo();
+        assert_eq!(table.transactions.len(), 1);
+    }
+
+    #[test]
+    fn loading_rejects_amounts_with_more_digits_than_the_currency() {
+        let file_path =
+            std::env::temp_dir().join(format!("hectec-{}-digits.csv", std::process::id()));
+        fs::write(
+            &file_path,
+            "date,amount,direction,currency\n2024.03.01,10.5,out,JPY\n",
+        )
+        .unwrap();
+        let mut table = TransactionsTable::new(
+            file_path.clone(),
+            DateFormat::default(),
+            3,
+            "ILS".to_string(),
+        );
+        let error = table.load().unwrap_err();
+        fs::remove_file(&file_path).unwrap();
+        assert!(format!("{:#}", error).contains("line 2: JPY has 0 decimal digits"));
+    }
+
+    #[test]
+    fn a_currency_the_amount_doesnt_fit_is_rejected() {
+        let mut refund = transaction("10.50", Direction::Out, "");
+        let currency = TransactionField::Currency as usize;
+        assert!(refund
+            .mutate_field(currency, "JPY", &DateFormat::default())
+            .is_err());
+        assert!(refund.set_fields(None, None, None, Some("JPY")).is_err());
+        assert_eq!(refund.currency(), "USD");
+
+        let mut refund = transaction("10", Direction::Out, "");
+        refund.splits = "shopping=9.50; fees=0.50".parse().unwrap();
+        assert!(refund.set_currency("JPY").is_err());
+        assert!(refund.set_currency("KWD").is_ok());
+        assert_eq!(refund.currency(), "KWD");
+    }
+
+    #[test]
+    fn new_rows_are_in_the_home_currency_unless_the_filter_has_one() {
+        let mut table =
+            TransactionsTable::new(PathBuf::new(), DateFormat::default(), 3, "ILS".to_string());
+        table.transactions = vec![transaction("50", Direction::Out, "")];
+        assert_eq!(table.new_transaction_from_filter().currency(), "ILS");
+        table.filter.currency = "USD".to_string();
+        assert_eq!(table.new_transaction_from_filter().currency(), "USD");
+    }
+
+    #[test]
+    fn cash_flow_and_accounts_flag_the_missing_exchange_rates() {
+        let mut salary = transaction("100", Direction::In, "");
+        salary.currency = "ILS".to_string();
+        let transactions = [salary, transaction("30", Direction::Out, "")];
+        let report = TransactionsReport::new(
+            &transactions,
+            "ILS",
+            &ExchangeRates::new(vec![]),
+            Grouping::Category,
+        );
+        let home = [ReportCurrency::Home("ILS".to_string())];
+        let cash_flow = CashFlow {
+            income: vec![Direction::In],
+            expense: vec![Direction::Out],
+        };
+        let rows = report.get_cash_flow_rows(None, &home, &cash_flow);
+        assert_eq!(rows[0][1], "\n100.00 !1\n");
+        assert_eq!(rows[1][1], "\n100% !1\n");
+        let rows = report.get_account_rows(None, &home);
+        assert_eq!(rows[0][1], "\n100.00 !1\n");
+    }
+
+    #[test]
+    fn loading_names_the_line_of_a_broken_record() {
+        let file_path =
+            std::env::temp_dir().join(format!("hectec-{}-records.csv", std::process::id()));
+        let load = |contents: &[u8]| {
+            fs::write(&file_path, contents).unwrap();
+            let mut table = TransactionsTable::new(
+                file_path.clone(),
+                DateFormat::default(),
+                3,
+                "ILS".to_string(),
+            );
+            format!("{:#}", table.load().unwrap_err())
+        };
+        let short_row = load(b"#schema:2\ndate,amount,direction\n2024.03.01,5,out\n2024.03.02,5\n");
+        let invalid_utf8 = load(b"date,amount,direction\n2024.03.01,5,out\n2024.03.02,5,\xff\n");
+        fs::remove_file(&file_path).unwrap();
+        assert!(short_row.contains("line 4: 2 fields, while the header has 3"));
+        assert!(invalid_utf8.contains("line 3: invalid UTF-8"));
+    }
+
+    #[test]
+    fn monthly_budgets_count_only_the_months_the_report_covers() {
+        let mut april = transaction("100", Direction::Out, "");
+        april.date.month = 4;
+        let transactions = [transaction("50", Direction::Out, ""), april];
+        let mut report = TransactionsReport::new(
+            &transactions,
+            "USD",
+            &ExchangeRates::new(vec![]),
+            Grouping::Category,
+        );
+        report.set_granularity(Granularity::Year);
+        let budgets = Budgets::new(vec![Budget {
+            direction: Direction::Out,
+            category: Some("shopping".to_string()),
+            amount: Money::parse("200").unwrap(),
+            period: BudgetPeriod::Monthly,
+        }]);
+        let shopping = (Some(Direction::Out), Some("shopping".to_string()));
+        let progress = report
+            .get_budget_progress(&shopping, Some(0), &budgets)
+            .unwrap();
+        assert_eq!(progress.budget, Money::parse("400").unwrap());
+        assert_eq!(progress.actual, Money::parse("150").unwrap());
+    }
+
+    #[test]
+    fn the_tags_filter_matches_whole_tags() {
+        let mut table =
+            TransactionsTable::new(PathBuf::new(), DateFormat::default(), 3, "ILS".to_string());
+        let mut carpool = transaction("10", Direction::Out, "");
+        carpool.tags = "carpool, Work".parse().unwrap();
+        let matches = |table: &mut TransactionsTable, tags: &str| {
+            table.filter.tags = tags.to_string();
+            table.matches_filter(&carpool)
+        };
+        assert!(matches(&mut table, ""));
+        assert!(matches(&mut table, "carpool"));
+        assert!(matches(&mut table, "work, CARPOOL"));
+        assert!(!matches(&mut table, "car"));
+        assert!(!matches(&mut table, "work, trip"));
+    }
+}